@@ -0,0 +1,391 @@
+use reqwest::{header::AUTHORIZATION, Client, RequestBuilder, Response, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::oauth2::OAuth2Config;
+use crate::signing::{AwsSigV4Config, HmacConfig};
+use crate::variables::VariableStore;
+
+/// Authentication scheme for a collection or a single request
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthConfig {
+    None,
+    Bearer {
+        token: String,
+        prefix: Option<String>,
+    },
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    ApiKey {
+        #[serde(default = "default_api_key_name")]
+        name: String,
+        value: String,
+        #[serde(default, rename = "in")]
+        location: ApiKeyLocation,
+    },
+    Digest {
+        username: String,
+        password: String,
+    },
+    #[serde(rename = "oauth2")]
+    OAuth2(OAuth2Config),
+    Hmac(HmacConfig),
+    #[serde(rename = "aws_sigv4")]
+    AwsSigV4(AwsSigV4Config),
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyLocation {
+    #[default]
+    Header,
+    Query,
+}
+
+fn default_api_key_name() -> String {
+    "X-API-Key".to_string()
+}
+
+impl AuthConfig {
+    /// Legacy auth: `req_variable_type` joined with the token file contents
+    pub fn legacy(prefix: Option<String>, token: String) -> Self {
+        AuthConfig::Bearer {
+            token: token.trim().to_string(),
+            prefix: Some(prefix.unwrap_or_default()),
+        }
+    }
+
+    /// Replace "{{VAR_NAME}}" placeholders in every credential field.
+    /// OAuth2 becomes a bearer token, fetched or refreshed when needed.
+    pub async fn resolve(
+        &self,
+        client: &Client,
+        variables: &VariableStore,
+    ) -> Result<AuthConfig, Box<dyn std::error::Error>> {
+        let resolve = |text: &str| variables.resolve_str(text);
+
+        Ok(match self {
+            AuthConfig::None => AuthConfig::None,
+            AuthConfig::Bearer { token, prefix } => AuthConfig::Bearer {
+                token: resolve(token)?,
+                prefix: prefix.clone(),
+            },
+            AuthConfig::Basic { username, password } => AuthConfig::Basic {
+                username: resolve(username)?,
+                password: password.as_deref().map(resolve).transpose()?,
+            },
+            AuthConfig::ApiKey {
+                name,
+                value,
+                location,
+            } => AuthConfig::ApiKey {
+                name: name.clone(),
+                value: resolve(value)?,
+                location: location.clone(),
+            },
+            AuthConfig::Digest { username, password } => AuthConfig::Digest {
+                username: resolve(username)?,
+                password: resolve(password)?,
+            },
+            AuthConfig::OAuth2(config) => AuthConfig::Bearer {
+                token: config
+                    .resolve(variables)?
+                    .access_token(client, variables)
+                    .await?,
+                prefix: None,
+            },
+            AuthConfig::Hmac(config) => AuthConfig::Hmac(config.resolve(variables)?),
+            AuthConfig::AwsSigV4(config) => AuthConfig::AwsSigV4(config.resolve(variables)?),
+        })
+    }
+
+    /// Attach the credentials, sign the final request when needed, and send it
+    pub async fn send(
+        &self,
+        client: &Client,
+        builder: RequestBuilder,
+    ) -> Result<Response, Box<dyn std::error::Error>> {
+        let mut request = self.apply(builder).build()?;
+
+        match self {
+            AuthConfig::Hmac(config) => config.sign(&mut request)?,
+            AuthConfig::AwsSigV4(config) => config.sign(&mut request)?,
+            _ => {}
+        }
+
+        Ok(client.execute(request).await?)
+    }
+
+    /// Attach the credentials to an outgoing request.
+    /// Digest sends nothing up front; it answers the server challenge instead.
+    /// Signing schemes are applied on the built request in `send`.
+    fn apply(&self, builder: RequestBuilder) -> RequestBuilder {
+        match self {
+            AuthConfig::None
+            | AuthConfig::Digest { .. }
+            | AuthConfig::OAuth2(_)
+            | AuthConfig::Hmac(_)
+            | AuthConfig::AwsSigV4(_) => builder,
+            AuthConfig::Bearer { token, prefix } => {
+                if token.trim().is_empty() {
+                    return builder;
+                }
+                let prefix = prefix.clone().unwrap_or_else(|| "Bearer".to_string());
+                let value = format!("{} {}", prefix, token.trim());
+                builder.header(AUTHORIZATION, value.trim())
+            }
+            AuthConfig::Basic { username, password } => {
+                builder.basic_auth(username, password.as_ref())
+            }
+            AuthConfig::ApiKey {
+                name,
+                value,
+                location,
+            } => match location {
+                ApiKeyLocation::Header => builder.header(name.as_str(), value.as_str()),
+                ApiKeyLocation::Query => builder.query(&[(name, value)]),
+            },
+        }
+    }
+}
+
+/// Build the Authorization header answering a `WWW-Authenticate: Digest ...` challenge
+pub fn digest_authorization(
+    method: &str,
+    url: &str,
+    username: &str,
+    password: &str,
+    challenge: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let params = parse_digest_challenge(challenge)
+        .ok_or_else(|| format!("Unsupported authentication challenge: {}", challenge))?;
+
+    let realm = params.get("realm").cloned().unwrap_or_default();
+    let nonce = params
+        .get("nonce")
+        .cloned()
+        .ok_or("Digest challenge has no nonce")?;
+    let algorithm = params
+        .get("algorithm")
+        .cloned()
+        .unwrap_or_else(|| "MD5".to_string());
+    let hash: fn(&str) -> String = match algorithm.to_uppercase().as_str() {
+        "MD5" | "MD5-SESS" => |data| format!("{:x}", md5::compute(data)),
+        "SHA-256" | "SHA-256-SESS" => |data| format!("{:x}", Sha256::digest(data)),
+        _ => return Err(format!("Unsupported digest algorithm: {}", algorithm).into()),
+    };
+
+    let parsed_url = Url::parse(url)?;
+    let uri = match parsed_url.query() {
+        Some(query) => format!("{}?{}", parsed_url.path(), query),
+        None => parsed_url.path().to_string(),
+    };
+
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let cnonce = hash(&nanos.to_string())[..16].to_string();
+    let nc = "00000001";
+
+    let mut ha1 = hash(&format!("{}:{}:{}", username, realm, password));
+    if algorithm.to_uppercase().ends_with("-SESS") {
+        ha1 = hash(&format!("{}:{}:{}", ha1, nonce, cnonce));
+    }
+    let ha2 = hash(&format!("{}:{}", method, uri));
+
+    // Only "auth" quality of protection is supported, "auth-int" needs the body hash
+    let qop = params
+        .get("qop")
+        .and_then(|qop| qop.split(',').map(str::trim).find(|q| *q == "auth"));
+
+    let response = match qop {
+        Some(qop) => hash(&format!(
+            "{}:{}:{}:{}:{}:{}",
+            ha1, nonce, nc, cnonce, qop, ha2
+        )),
+        None => hash(&format!("{}:{}:{}", ha1, nonce, ha2)),
+    };
+
+    let mut header = format!(
+        "Digest username=\"{}\", realm=\"{}\", nonce=\"{}\", uri=\"{}\", algorithm={}, response=\"{}\"",
+        username, realm, nonce, uri, algorithm, response
+    );
+    if let Some(qop) = qop {
+        header.push_str(&format!(", qop={}, nc={}, cnonce=\"{}\"", qop, nc, cnonce));
+    }
+    if let Some(opaque) = params.get("opaque") {
+        header.push_str(&format!(", opaque=\"{}\"", opaque));
+    }
+
+    Ok(header)
+}
+
+/// Parse `Digest key="value", key=value` into a map
+fn parse_digest_challenge(challenge: &str) -> Option<HashMap<String, String>> {
+    let rest = challenge.trim();
+    if !rest.get(..6)?.eq_ignore_ascii_case("digest") {
+        return None;
+    }
+
+    let mut params = HashMap::new();
+    let mut chars = rest[6..].chars().peekable();
+
+    loop {
+        // Skip separators between parameters
+        while matches!(chars.peek(), Some(c) if *c == ',' || c.is_whitespace()) {
+            chars.next();
+        }
+
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if key.trim().is_empty() {
+            break;
+        }
+
+        let value = if chars.peek() == Some(&'"') {
+            chars.next();
+            let mut value = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    _ => value.push(c),
+                }
+            }
+            value
+        } else {
+            chars.by_ref().take_while(|c| *c != ',').collect()
+        };
+
+        params.insert(key.trim().to_lowercase(), value.trim().to_string());
+    }
+
+    Some(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHALLENGE: &str = r#"Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#;
+
+    fn md5_hex(data: &str) -> String {
+        format!("{:x}", md5::compute(data))
+    }
+
+    #[test]
+    fn digest_challenge_parameters_are_parsed() {
+        let params = parse_digest_challenge(CHALLENGE).unwrap();
+        assert_eq!(params["realm"], "testrealm@host.com");
+        assert_eq!(params["qop"], "auth,auth-int");
+        assert_eq!(params["nonce"], "dcd98b7102dd2f0e8b11d0f600bfb0c093");
+        assert_eq!(params["opaque"], "5ccc069c403ebaf9f0171e9517f40e41");
+
+        let params =
+            parse_digest_challenge(r#"digest Realm="a \"b\", c", algorithm=SHA-256 ,nonce=n1"#)
+                .unwrap();
+        assert_eq!(params["realm"], r#"a "b", c"#);
+        assert_eq!(params["algorithm"], "SHA-256");
+        assert_eq!(params["nonce"], "n1");
+
+        assert!(parse_digest_challenge(r#"Basic realm="a""#).is_none());
+        assert!(parse_digest_challenge("").is_none());
+    }
+
+    #[test]
+    fn digest_response_without_qop_matches_rfc_2069() {
+        let header = digest_authorization(
+            "GET",
+            "http://host.com/dir/index.html",
+            "Mufasa",
+            "Circle Of Life",
+            r#"Digest realm="testrealm@host.com", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093""#,
+        )
+        .unwrap();
+
+        let ha1 = md5_hex("Mufasa:testrealm@host.com:Circle Of Life");
+        let ha2 = md5_hex("GET:/dir/index.html");
+        let expected = md5_hex(&format!(
+            "{}:dcd98b7102dd2f0e8b11d0f600bfb0c093:{}",
+            ha1, ha2
+        ));
+        assert!(header.contains(&format!("response=\"{}\"", expected)));
+        assert!(!header.contains("qop="));
+    }
+
+    #[test]
+    fn digest_response_with_qop_auth_matches_rfc_2617() {
+        let header = digest_authorization(
+            "GET",
+            "http://host.com/dir/index.html",
+            "Mufasa",
+            "Circle Of Life",
+            CHALLENGE,
+        )
+        .unwrap();
+
+        // The client nonce is random, so recompute the RFC 2617 response with it
+        let params = parse_digest_challenge(&header).unwrap();
+        assert_eq!(params["uri"], "/dir/index.html");
+        assert_eq!(params["qop"], "auth");
+        assert_eq!(params["nc"], "00000001");
+        assert_eq!(params["opaque"], "5ccc069c403ebaf9f0171e9517f40e41");
+        let ha1 = md5_hex("Mufasa:testrealm@host.com:Circle Of Life");
+        let ha2 = md5_hex("GET:/dir/index.html");
+        let expected = md5_hex(&format!(
+            "{}:dcd98b7102dd2f0e8b11d0f600bfb0c093:00000001:{}:auth:{}",
+            ha1, params["cnonce"], ha2
+        ));
+        assert_eq!(params["response"], expected);
+    }
+
+    #[test]
+    fn digest_rejects_unsupported_challenges() {
+        let url = "http://host.com/";
+        assert!(digest_authorization("GET", url, "u", "p", r#"Basic realm="a""#).is_err());
+        assert!(digest_authorization("GET", url, "u", "p", r#"Digest realm="a""#).is_err());
+        assert!(
+            digest_authorization("GET", url, "u", "p", r#"Digest nonce="n", algorithm=SHA-1"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn api_key_is_sent_in_a_header_or_the_query() {
+        let client = Client::new();
+        let api_key = |location| AuthConfig::ApiKey {
+            name: "key".to_string(),
+            value: "a b&c".to_string(),
+            location,
+        };
+
+        let request = api_key(ApiKeyLocation::Header)
+            .apply(client.get("http://host.com/items?page=2"))
+            .build()
+            .unwrap();
+        assert_eq!(request.headers()["key"], "a b&c");
+        assert_eq!(request.url().query(), Some("page=2"));
+
+        let request = api_key(ApiKeyLocation::Query)
+            .apply(client.get("http://host.com/items?page=2"))
+            .build()
+            .unwrap();
+        assert!(request.headers().get("key").is_none());
+        assert_eq!(request.url().query(), Some("page=2&key=a+b%26c"));
+    }
+
+    #[test]
+    fn api_key_location_defaults_to_the_header() {
+        let auth: AuthConfig =
+            serde_json::from_str(r#"{"type": "api_key", "value": "secret"}"#).unwrap();
+        match auth {
+            AuthConfig::ApiKey { name, location, .. } => {
+                assert_eq!(name, "X-API-Key");
+                assert_eq!(location, ApiKeyLocation::Header);
+            }
+            other => panic!("expected api_key, got {:?}", other),
+        }
+    }
+}
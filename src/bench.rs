@@ -0,0 +1,336 @@
+use colored::Colorize;
+use futures_util::{stream, StreamExt};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+use crate::collection::{AppMainRequest, RequestData};
+use crate::request::{
+    build_client, build_request, build_url, digest_challenge_auth, select_auth, RequestOverrides,
+};
+use crate::variables::VariableStore;
+
+const HISTOGRAM_BUCKETS: usize = 10;
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// How a `bench` run fires its requests
+pub struct BenchConfig {
+    /// Total number of requests
+    pub requests: usize,
+    /// Requests in flight at once
+    pub concurrency: usize,
+    /// Requests per second for fixed-rate mode, as fast as possible when None
+    pub rate: Option<f64>,
+}
+
+/// The outcome of one request: a status code or an error message
+type Sample = (Duration, Result<u16, String>);
+
+pub struct BenchReport {
+    elapsed: Duration,
+    samples: Vec<Sample>,
+}
+
+/// Fire `send` `config.requests` times with at most `config.concurrency` in flight.
+/// In fixed-rate mode request `i` is not started before `i / rate` seconds.
+pub async fn run_bench<F, Fut>(config: &BenchConfig, send: F) -> BenchReport
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<u16, String>>,
+{
+    let started = Instant::now();
+
+    let samples = stream::iter(0..config.requests)
+        .map(|index| {
+            let send = &send;
+            async move {
+                if let Some(rate) = config.rate {
+                    let scheduled = started + Duration::from_secs_f64(index as f64 / rate);
+                    sleep(scheduled.saturating_duration_since(Instant::now())).await;
+                }
+
+                let request_started = Instant::now();
+                let outcome = send().await;
+                (request_started.elapsed(), outcome)
+            }
+        })
+        .buffer_unordered(config.concurrency.max(1))
+        .collect()
+        .await;
+
+    BenchReport {
+        elapsed: started.elapsed(),
+        samples,
+    }
+}
+
+/// Resolve a request once (URL, auth, body), then send copies of it as `config` asks
+pub async fn bench_request(
+    app_main_request: &AppMainRequest,
+    request: &RequestData,
+    variables: &VariableStore,
+    config: &BenchConfig,
+) -> Result<BenchReport, Box<dyn std::error::Error>> {
+    let client = build_client(app_main_request, request, &None)?;
+    let main_url = build_url(
+        app_main_request,
+        request,
+        variables,
+        &RequestOverrides::default(),
+    )?;
+    let auth = select_auth(app_main_request, request, variables)
+        .resolve(&client, variables)
+        .await?;
+    let builder = build_request(
+        &client,
+        &request.req_type,
+        main_url.clone(),
+        app_main_request.create_header_map(),
+        variables,
+        request.req_body.clone(),
+    )?;
+    if builder.try_clone().is_none() {
+        return Err("This request body cannot be sent more than once".into());
+    }
+
+    let mode = match config.rate {
+        Some(rate) => format!(
+            "{} requests at {} req/s, up to {} in flight",
+            config.requests, rate, config.concurrency
+        ),
+        None => format!(
+            "{} requests, {} in flight",
+            config.requests, config.concurrency
+        ),
+    };
+    println!();
+    println!(
+        "{} {}",
+        "TITLE    :".blue().bold(),
+        request.req_title.green()
+    );
+    println!(
+        "{} {}",
+        "URL      :".blue().bold(),
+        variables.mask_str(&main_url).yellow()
+    );
+    println!("{} {}", "MODE     :".blue().bold(), mode);
+
+    Ok(run_bench(config, || async {
+        let copy = || builder.try_clone().ok_or("request could not be copied");
+        let mut response = auth
+            .send(&client, copy()?)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        // Digest auth: every copy answers its own challenge, like a real client
+        let digest_auth = digest_challenge_auth(&auth, &request.req_type, &main_url, &response)
+            .map_err(|err| err.to_string())?;
+        if let Some(digest_auth) = digest_auth {
+            response = digest_auth
+                .send(&client, copy()?)
+                .await
+                .map_err(|err| err.to_string())?;
+        }
+        let status = response.status().as_u16();
+        response.bytes().await.map_err(|err| err.to_string())?;
+        Ok(status)
+    })
+    .await)
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// The latency below which `percent` of the sorted latencies fall
+fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = ((percent / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl BenchReport {
+    /// Print throughput, latency percentiles, a latency histogram and the status codes
+    pub fn display(&self) {
+        let mut latencies: Vec<Duration> =
+            self.samples.iter().map(|(latency, _)| *latency).collect();
+        latencies.sort();
+
+        let total = self.samples.len();
+        let succeeded = self
+            .samples
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, Ok(status) if (200..300).contains(status)))
+            .count();
+        let throughput = total as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON);
+
+        println!();
+        println!("{} {}", "REQUESTS :".blue().bold(), total);
+        println!(
+            "{} {:.2}s",
+            "DURATION :".blue().bold(),
+            self.elapsed.as_secs_f64()
+        );
+        println!("{} {:.2} req/s", "RATE     :".blue().bold(), throughput);
+        println!(
+            "{} {} 2xx, {} other",
+            "SUCCESS  :".blue().bold(),
+            succeeded.to_string().green(),
+            (total - succeeded).to_string().red()
+        );
+
+        if latencies.is_empty() {
+            return;
+        }
+        let mean = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+
+        println!();
+        println!("{}", "Latency  :".blue().bold());
+        println!("  min  {}", millis(latencies[0]).yellow());
+        println!("  mean {}", millis(mean).yellow());
+        println!("  p50  {}", millis(percentile(&latencies, 50.0)).yellow());
+        println!("  p90  {}", millis(percentile(&latencies, 90.0)).yellow());
+        println!("  p99  {}", millis(percentile(&latencies, 99.0)).yellow());
+        println!("  max  {}", millis(latencies[latencies.len() - 1]).yellow());
+
+        self.display_histogram(&latencies);
+
+        let mut outcomes: BTreeMap<String, usize> = BTreeMap::new();
+        for (_, outcome) in &self.samples {
+            let key = match outcome {
+                Ok(status) => status.to_string(),
+                Err(err) => format!("error: {}", err),
+            };
+            *outcomes.entry(key).or_default() += 1;
+        }
+
+        println!();
+        println!("{}", "Status   :".blue().bold());
+        for (outcome, count) in outcomes {
+            let outcome = if outcome.starts_with('2') {
+                outcome.green()
+            } else {
+                outcome.red()
+            };
+            println!("  {} x {}", outcome, count);
+        }
+        println!();
+    }
+
+    fn display_histogram(&self, sorted: &[Duration]) {
+        let (min, max) = (sorted[0], sorted[sorted.len() - 1]);
+        let step = (max - min) / HISTOGRAM_BUCKETS as u32;
+
+        let mut counts = [0usize; HISTOGRAM_BUCKETS];
+        for latency in sorted {
+            let bucket = if step.is_zero() {
+                0
+            } else {
+                ((*latency - min).as_nanos() / step.as_nanos()) as usize
+            };
+            counts[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1;
+        }
+        let largest = counts.iter().copied().max().unwrap_or(1).max(1);
+
+        println!();
+        println!("{}", "Histogram:".blue().bold());
+        for (bucket, count) in counts.iter().enumerate() {
+            if step.is_zero() && bucket > 0 {
+                break;
+            }
+            let upper = min + step * (bucket as u32 + 1);
+            let bar = "■".repeat(count * HISTOGRAM_WIDTH / largest);
+            println!("  {:>12} | {} {}", millis(upper), bar.green(), count);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A stand-in server: 401 with a digest challenge, 200 once the request answers it
+    async fn digest_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                        match socket.read(&mut buffer).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(read) => request.extend_from_slice(&buffer[..read]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request).to_lowercase();
+                    let response = if request.contains("authorization: digest") {
+                        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+                    } else {
+                        "HTTP/1.1 401 Unauthorized\r\n\
+                         WWW-Authenticate: Digest realm=\"bench\", nonce=\"abc\", qop=\"auth\"\r\n\
+                         Content-Length: 0\r\nConnection: close\r\n\r\n"
+                    };
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn bench_answers_digest_challenges() {
+        let base_url = digest_server().await;
+        let app_main_request: AppMainRequest = serde_json::from_value(json!({
+            "base_url": base_url,
+            "auth": {"type": "digest", "username": "user", "password": "secret"},
+            "requests": [{
+                "req_tag": "ping",
+                "req_title": "Ping",
+                "req_type": "GET",
+                "req_end_point": "/ping"
+            }]
+        }))
+        .unwrap();
+        let request = app_main_request.requests[0].clone();
+        let variable_dir = std::env::temp_dir().join("rest_api_bench_test");
+        let variables = VariableStore::new(&variable_dir.to_string_lossy());
+        let config = BenchConfig {
+            requests: 6,
+            concurrency: 3,
+            rate: None,
+        };
+
+        let report = bench_request(&app_main_request, &request, &variables, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(report.samples.len(), 6);
+        assert!(report
+            .samples
+            .iter()
+            .all(|(_, outcome)| outcome == &Ok(200)));
+    }
+
+    #[tokio::test]
+    async fn fixed_rate_spaces_out_the_requests() {
+        let config = BenchConfig {
+            requests: 3,
+            concurrency: 3,
+            rate: Some(20.0),
+        };
+
+        let report = run_bench(&config, || async { Ok(204) }).await;
+
+        assert_eq!(report.samples.len(), 3);
+        assert!(report.elapsed >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,261 @@
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, SET_COOKIE},
+    Response, StatusCode,
+};
+use serde_json::Value;
+use std::io;
+
+use crate::file_ops::get_nested_value;
+
+/// Prefixes of the sources understood by `req_variable_response_value`, besides `status`;
+/// anything else is a JSON body path
+const SOURCES: [&str; 5] = ["body:", "header:", "cookie:", "regex:", "xpath:"];
+
+/// The parts of a response that values can be captured from
+pub struct CapturedResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub text: String,
+    pub body: Value,
+    /// Whether `body` was parsed from the text, rather than the placeholder for a non-JSON body
+    is_json: bool,
+}
+
+impl CapturedResponse {
+    /// Read the whole response, the body is parsed as JSON when possible
+    pub async fn read(response: Response) -> io::Result<CapturedResponse> {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let text = response.text().await.map_err(io::Error::other)?;
+        Ok(CapturedResponse::from_parts(status, headers, text))
+    }
+
+    pub fn from_parts(status: StatusCode, headers: HeaderMap, text: String) -> CapturedResponse {
+        let (body, is_json) = match serde_json::from_str(&text) {
+            Ok(body) => (body, true),
+            Err(_) => (
+                serde_json::json!({"error": "Failed to parse response as JSON"}),
+                false,
+            ),
+        };
+
+        CapturedResponse {
+            status,
+            headers,
+            text,
+            body,
+            is_json,
+        }
+    }
+
+    /// The JSON body, or the text as a JSON string when the body is not JSON
+    pub fn json_or_text(&self) -> Value {
+        serde_json::from_str(&self.text).unwrap_or_else(|_| Value::String(self.text.clone()))
+    }
+
+    /// Capture a value with a source such as `data.id`, `header:Location`, `cookie:SESSION`,
+    /// `status`, `regex:id=(\d+)` or `xpath:/user/id`.
+    /// `| regex:PATTERN` after any source keeps only the first group of the match,
+    /// e.g. `header:Location | regex:/users/(\d+)`.
+    pub fn capture(&self, source: &str) -> io::Result<Option<String>> {
+        let (source, filter) = match source.split_once("| regex:") {
+            Some((source, pattern)) => (source.trim(), Some(pattern.trim())),
+            None => (source.trim(), None),
+        };
+
+        let value = if source == "status" {
+            Some(self.status.as_u16().to_string())
+        } else if let Some(name) = source.strip_prefix("header:") {
+            self.headers
+                .get(name.trim())
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        } else if let Some(name) = source.strip_prefix("cookie:") {
+            self.cookie(name.trim())
+        } else if let Some(pattern) = source.strip_prefix("regex:") {
+            regex_capture(pattern, &self.text)?
+        } else if let Some(expression) = source.strip_prefix("xpath:") {
+            xpath_capture(expression, &self.text)?
+        } else if !self.is_json {
+            None
+        } else {
+            let path = source.strip_prefix("body:").unwrap_or(source);
+            match get_nested_value(&self.body, path) {
+                Some(Value::String(text)) => Some(text.clone()),
+                Some(Value::Null) | None => None,
+                Some(other) => Some(other.to_string()),
+            }
+        };
+
+        match (value, filter) {
+            (Some(value), Some(pattern)) => regex_capture(pattern, &value),
+            (value, _) => Ok(value),
+        }
+    }
+
+    /// The value of a cookie set by this response
+    fn cookie(&self, name: &str) -> Option<String> {
+        self.headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| value.split(';').next()?.split_once('='))
+            .find(|(cookie_name, _)| cookie_name.trim() == name)
+            .map(|(_, value)| value.trim().to_string())
+    }
+}
+
+/// Whether a `req_variable_response_value` string is a single capture source
+/// rather than the "FILE:path,FILE:path" mapping shorthand
+pub fn is_capture_source(text: &str) -> bool {
+    let text = text.trim();
+    !text.contains(':') || text == "status" || SOURCES.iter().any(|source| text.starts_with(source))
+}
+
+/// Split the "FILE:source,FILE:source" shorthand into its entries.
+/// Only a comma followed by `FILE:` starts a new entry, so `regex:\d{1,3}` stays whole.
+pub fn split_mapping_shorthand(text: &str) -> Vec<&str> {
+    let starts_entry = |rest: &str| {
+        rest.trim_start().split_once(':').is_some_and(|(name, _)| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/'))
+                && !SOURCES.contains(&format!("{}:", name).as_str())
+        })
+    };
+
+    let mut entries = Vec::new();
+    let mut start = 0;
+    for (index, _) in text.match_indices(',') {
+        if starts_entry(&text[index + 1..]) {
+            entries.push(&text[start..index]);
+            start = index + 1;
+        }
+    }
+    entries.push(&text[start..]);
+    entries
+}
+
+/// The first capture group of the match, or the whole match without groups
+fn regex_capture(pattern: &str, text: &str) -> io::Result<Option<String>> {
+    let regex = Regex::new(pattern).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid regex '{}': {}", pattern, e),
+        )
+    })?;
+
+    Ok(regex.captures(text).and_then(|captures| {
+        captures
+            .get(1)
+            .or_else(|| captures.get(0))
+            .map(|value| value.as_str().to_string())
+    }))
+}
+
+/// The string value of an XPath expression on an XML body
+fn xpath_capture(expression: &str, text: &str) -> io::Result<Option<String>> {
+    let package = sxd_document::parser::parse(text).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Response is not valid XML: {:?}", e),
+        )
+    })?;
+    let document = package.as_document();
+    let value = sxd_xpath::evaluate_xpath(&document, expression).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid XPath '{}': {}", expression, e),
+        )
+    })?;
+
+    let value = value.string();
+    Ok(if value.is_empty() { None } else { Some(value) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_is_a_source_only_on_its_own() {
+        assert!(is_capture_source("status"));
+        assert!(is_capture_source("header:x-request-id"));
+        assert!(is_capture_source("data.token"));
+        assert!(!is_capture_source("status_msg.txt:data.msg"));
+        assert!(!is_capture_source("status.txt:data.status,id.txt:data.id"));
+    }
+
+    fn response(text: &str) -> CapturedResponse {
+        let mut headers = HeaderMap::new();
+        headers.insert("Location", "/users/123".parse().unwrap());
+        headers.append(SET_COOKIE, "theme=dark; Path=/".parse().unwrap());
+        headers.append(SET_COOKIE, "SESSION=abc; HttpOnly".parse().unwrap());
+        CapturedResponse::from_parts(StatusCode::CREATED, headers, text.to_string())
+    }
+
+    #[test]
+    fn headers_cookies_and_status_are_captured() {
+        let response = response("{}");
+        let capture = |source| response.capture(source).unwrap();
+        assert_eq!(capture("status").as_deref(), Some("201"));
+        assert_eq!(capture("header:location").as_deref(), Some("/users/123"));
+        assert_eq!(capture("header: Location").as_deref(), Some("/users/123"));
+        assert_eq!(capture("header:X-Missing"), None);
+        assert_eq!(capture("cookie:SESSION").as_deref(), Some("abc"));
+        assert_eq!(capture("cookie:theme").as_deref(), Some("dark"));
+        assert_eq!(capture("cookie:missing"), None);
+        assert_eq!(
+            capture(r"header:Location | regex:/users/(\d+)").as_deref(),
+            Some("123")
+        );
+    }
+
+    #[test]
+    fn regex_captures_the_first_group_or_the_whole_match() {
+        let response = response("order id=42, total=9.50");
+        let capture = |source| response.capture(source).unwrap();
+        assert_eq!(capture(r"regex:id=(\d+)").as_deref(), Some("42"));
+        assert_eq!(capture(r"regex:\d+\.\d+").as_deref(), Some("9.50"));
+        assert_eq!(capture(r"regex:\d{1,3}").as_deref(), Some("42"));
+        assert_eq!(capture("regex:missing=(.*)"), None);
+        assert!(response.capture("regex:(").is_err());
+    }
+
+    #[test]
+    fn json_paths_read_only_a_json_body() {
+        let json = response(r#"{"data": {"id": 7, "tags": ["a"], "none": null}}"#);
+        assert_eq!(json.capture("data.id").unwrap().as_deref(), Some("7"));
+        assert_eq!(
+            json.capture("body:data.tags.0").unwrap().as_deref(),
+            Some("a")
+        );
+        assert_eq!(json.capture("data.none").unwrap(), None);
+
+        let text = response("Internal Server Error");
+        assert_eq!(text.capture("error").unwrap(), None);
+        assert_eq!(text.capture("body:error").unwrap(), None);
+    }
+
+    #[test]
+    fn the_shorthand_splits_only_before_a_file_name() {
+        assert_eq!(
+            split_mapping_shorthand("id.txt:data.id,name.txt:data.name"),
+            ["id.txt:data.id", "name.txt:data.name"]
+        );
+        assert_eq!(
+            split_mapping_shorthand(r"code.txt:regex:\d{1,3},loc.txt:header:Location"),
+            [r"code.txt:regex:\d{1,3}", "loc.txt:header:Location"]
+        );
+        assert_eq!(
+            split_mapping_shorthand("a.txt:regex:x, y,header:Location"),
+            ["a.txt:regex:x, y,header:Location"]
+        );
+        assert_eq!(
+            split_mapping_shorthand("users/ID.txt:data.id, TOKEN:data.token"),
+            ["users/ID.txt:data.id", " TOKEN:data.token"]
+        );
+    }
+}
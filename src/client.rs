@@ -0,0 +1,108 @@
+use colored::Colorize;
+use reqwest::{
+    redirect::Policy,
+    tls::{Certificate, Identity},
+    ClientBuilder, NoProxy, Proxy,
+};
+use serde::Deserialize;
+use std::fs;
+use std::time::Duration;
+
+use crate::file_ops::expand_tilde;
+
+/// HTTP client settings for a collection, overridable per request
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ClientConfig {
+    connect_timeout_ms: Option<u64>,
+    timeout_ms: Option<u64>,
+    read_timeout_ms: Option<u64>,
+    follow_redirects: Option<bool>,
+    max_redirects: Option<usize>,
+    http_proxy: Option<String>,
+    https_proxy: Option<String>,
+    no_proxy: Option<String>,
+    ca_bundle: Option<String>,
+    client_cert: Option<String>,
+    client_key: Option<String>,
+    insecure: Option<bool>,
+}
+
+impl ClientConfig {
+    /// Combine with request settings, every field set on the request wins
+    pub fn merge(&self, request: &ClientConfig) -> ClientConfig {
+        ClientConfig {
+            connect_timeout_ms: request.connect_timeout_ms.or(self.connect_timeout_ms),
+            timeout_ms: request.timeout_ms.or(self.timeout_ms),
+            read_timeout_ms: request.read_timeout_ms.or(self.read_timeout_ms),
+            follow_redirects: request.follow_redirects.or(self.follow_redirects),
+            max_redirects: request.max_redirects.or(self.max_redirects),
+            http_proxy: request.http_proxy.clone().or(self.http_proxy.clone()),
+            https_proxy: request.https_proxy.clone().or(self.https_proxy.clone()),
+            no_proxy: request.no_proxy.clone().or(self.no_proxy.clone()),
+            ca_bundle: request.ca_bundle.clone().or(self.ca_bundle.clone()),
+            client_cert: request.client_cert.clone().or(self.client_cert.clone()),
+            client_key: request.client_key.clone().or(self.client_key.clone()),
+            insecure: request.insecure.or(self.insecure),
+        }
+    }
+
+    /// Start a client builder with these settings applied
+    pub fn builder(&self) -> Result<ClientBuilder, Box<dyn std::error::Error>> {
+        let mut builder = reqwest::Client::builder();
+
+        if let Some(connect_timeout_ms) = self.connect_timeout_ms {
+            builder = builder.connect_timeout(Duration::from_millis(connect_timeout_ms));
+        }
+        if let Some(timeout_ms) = self.timeout_ms {
+            builder = builder.timeout(Duration::from_millis(timeout_ms));
+        }
+        if let Some(read_timeout_ms) = self.read_timeout_ms {
+            builder = builder.read_timeout(Duration::from_millis(read_timeout_ms));
+        }
+
+        if !self.follow_redirects.unwrap_or(true) {
+            builder = builder.redirect(Policy::none());
+        } else if let Some(max_redirects) = self.max_redirects {
+            builder = builder.redirect(Policy::limited(max_redirects));
+        }
+
+        let no_proxy = self.no_proxy.as_deref().and_then(NoProxy::from_string);
+        if let Some(http_proxy) = &self.http_proxy {
+            builder = builder.proxy(Proxy::http(http_proxy)?.no_proxy(no_proxy.clone()));
+        }
+        if let Some(https_proxy) = &self.https_proxy {
+            builder = builder.proxy(Proxy::https(https_proxy)?.no_proxy(no_proxy));
+        }
+
+        if let Some(ca_bundle) = &self.ca_bundle {
+            let pem = fs::read(expand_tilde(ca_bundle))
+                .map_err(|e| format!("Error reading CA bundle '{}': {}", ca_bundle, e))?;
+            for certificate in Certificate::from_pem_bundle(&pem)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        match (&self.client_cert, &self.client_key) {
+            (Some(client_cert), Some(client_key)) => {
+                let cert = fs::read(expand_tilde(client_cert))
+                    .map_err(|e| format!("Error reading client cert '{}': {}", client_cert, e))?;
+                let key = fs::read(expand_tilde(client_key))
+                    .map_err(|e| format!("Error reading client key '{}': {}", client_key, e))?;
+                builder = builder.identity(Identity::from_pkcs8_pem(&cert, &key)?);
+            }
+            (None, None) => {}
+            _ => return Err("client_cert and client_key must be set together".into()),
+        }
+
+        if self.insecure.unwrap_or_default() {
+            eprintln!(
+                "{} {}",
+                "WARNING  :".red().bold(),
+                "TLS certificate checks are disabled (insecure)".red()
+            );
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+
+        Ok(builder)
+    }
+}
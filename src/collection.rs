@@ -0,0 +1,149 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use crate::auth::AuthConfig;
+use crate::client::ClientConfig;
+use crate::file_ops::{expand_tilde, resolve_relative};
+use crate::history::{history_path, History, HistoryConfig};
+use crate::mock::MockConfig;
+use crate::retry::RetryConfig;
+use crate::schema::SchemaConfig;
+use crate::secrets::SecretsConfig;
+use crate::snapshot::SnapshotConfig;
+use crate::variables::VariableStore;
+use crate::workflow::WorkflowConfig;
+
+/// A collection: the API FILE passed with `--file`
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AppMainRequest {
+    pub base_url: String,
+    pub headers: Option<HashMap<String, String>>,
+    pub variable_dir: Option<String>,
+    pub variable_access_token_file: Option<String>,
+    pub auth: Option<AuthConfig>,
+    pub cookie_jar: Option<bool>,
+    pub history: Option<HistoryConfig>,
+    pub client: Option<ClientConfig>,
+    pub secrets: Option<SecretsConfig>,
+    pub snapshot: Option<SnapshotConfig>,
+    /// OpenAPI document for the `"schema": { "component": ... }` of requests
+    pub openapi: Option<String>,
+    pub workflows: Option<Vec<WorkflowConfig>>,
+    pub requests: Vec<RequestData>,
+}
+
+impl AppMainRequest {
+    /// Read a collection from a JSON file, `~` is expanded.
+    /// Relative `body_file`, `example_file` and snapshot `dir` paths are looked up next to it first.
+    pub fn load(file_data: &str) -> io::Result<AppMainRequest> {
+        let path = expand_tilde(file_data);
+        let file = File::open(&path)?;
+        let reader = BufReader::new(file);
+        let mut app_main_request: AppMainRequest = serde_json::from_reader(reader)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let collection_dir = Path::new(&path).parent().unwrap_or(Path::new(""));
+        if let Some(snapshot) = &mut app_main_request.snapshot {
+            snapshot.resolve_files(collection_dir);
+        }
+        for request in &mut app_main_request.requests {
+            if let Some(req_body) = &mut request.req_body {
+                req_body.body_file = resolve_relative(collection_dir, &req_body.body_file);
+            }
+            if let Some(mock) = &mut request.mock {
+                mock.resolve_files(collection_dir);
+            }
+            if let Some(snapshot) = &mut request.snapshot {
+                snapshot.resolve_files(collection_dir);
+            }
+        }
+        Ok(app_main_request)
+    }
+
+    /// The request with this `req_tag`
+    pub fn request(&self, tag: &str) -> Option<&RequestData> {
+        self.requests.iter().find(|&item| item.req_tag == tag)
+    }
+
+    /// The workflow with this name
+    pub fn workflow(&self, name: &str) -> Option<&WorkflowConfig> {
+        self.workflows
+            .iter()
+            .flatten()
+            .find(|workflow| workflow.name == name)
+    }
+
+    /// The variables of `variable_dir`, with the collection secrets settings
+    pub fn variables(&self) -> VariableStore {
+        VariableStore::new(&self.variable_dir.clone().unwrap_or_default())
+            .with_secrets(self.secrets.clone())
+    }
+
+    /// The history file next to `variable_dir`, none is kept without one
+    pub fn history(&self) -> History {
+        match self.variable_dir.as_deref().filter(|dir| !dir.is_empty()) {
+            Some(variable_dir) => History::new(
+                &history_path(variable_dir),
+                &self.history.clone().unwrap_or_default(),
+            ),
+            None => History::disabled(),
+        }
+    }
+
+    /// The snapshot settings of a request on top of the collection ones
+    pub fn snapshot_config(&self, request: &RequestData) -> SnapshotConfig {
+        self.snapshot
+            .clone()
+            .unwrap_or_default()
+            .merge(&request.snapshot.clone().unwrap_or_default())
+    }
+
+    pub fn create_header_map(&self) -> HeaderMap {
+        let mut header_map = HeaderMap::new();
+
+        if let Some(headers) = &self.headers {
+            for (key, value) in headers {
+                if let Ok(header_name) = HeaderName::from_bytes(key.as_bytes()) {
+                    if let Ok(header_value) = HeaderValue::from_str(value) {
+                        header_map.insert(header_name, header_value);
+                    }
+                }
+            }
+        }
+
+        header_map
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RequestData {
+    pub req_tag: String,
+    pub req_title: String,
+    pub req_type: String,
+    pub req_end_point: String,
+    pub req_params: Option<String>,
+    pub req_query: Option<Map<String, Value>>,
+    pub req_path: Option<Map<String, Value>>,
+    pub req_variable_type: Option<String>,
+    pub req_variable_is_save: Option<bool>,
+    pub req_variable_response_value: Option<Value>,
+    pub req_body: Option<RequestDataBody>,
+    pub auth: Option<AuthConfig>,
+    pub client: Option<ClientConfig>,
+    pub retry: Option<RetryConfig>,
+    pub mock: Option<MockConfig>,
+    pub snapshot: Option<SnapshotConfig>,
+    pub schema: Option<SchemaConfig>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RequestDataBody {
+    pub body_type: String,
+    pub body_file: String,
+    pub file_paths: Option<Vec<String>>,
+}
@@ -0,0 +1,153 @@
+use colored::Colorize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::collection::AppMainRequest;
+use crate::history::{History, HistoryEntry};
+use crate::request::RequestOverrides;
+use crate::snapshot;
+use crate::variables::VariableStore;
+
+/// One response of `compare`
+pub struct Side {
+    pub label: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Value,
+}
+
+impl Side {
+    /// The response of a history entry
+    pub fn from_history(history: &History, id: u64) -> Result<Side, String> {
+        let entry = history
+            .find(id)
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("History entry not found: {}", id))?;
+        Side::from_entry(&entry).ok_or_else(|| format!("History entry {} has no response", id))
+    }
+
+    /// A history entry, None when it has no response
+    pub fn from_entry(entry: &HistoryEntry) -> Option<Side> {
+        Some(Side {
+            label: format!("#{} {} {}", entry.id, entry.local_time(), entry.request.url),
+            status: entry.status?,
+            headers: entry.response_headers.clone(),
+            body: serde_json::from_str(&entry.response_body)
+                .unwrap_or_else(|_| Value::String(entry.response_body.clone())),
+        })
+    }
+
+    /// Send the tag with the variables and auth of its collection. Nothing is captured
+    /// or saved to the history, and no cookies are kept between the two sides.
+    pub async fn send(
+        app_main_request: &AppMainRequest,
+        tag: &str,
+        variables: &VariableStore,
+        overrides: &RequestOverrides,
+    ) -> Result<Side, String> {
+        let request = app_main_request
+            .request(tag)
+            .ok_or_else(|| format!("Item not found: {}", tag))?;
+        let resolved = app_main_request
+            .resolve(request, variables, overrides)
+            .map_err(|e| variables.mask_str(&e.to_string()))?;
+
+        let captured = app_main_request
+            .execute(request, variables, &None, overrides)
+            .await
+            .map_err(|e| variables.mask_str(&e.to_string()))?;
+        Ok(Side {
+            label: variables.mask_str(&resolved.url),
+            status: captured.status.as_u16(),
+            headers: captured
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), variables.mask_str(value.to_str().ok()?)))
+                })
+                .collect(),
+            body: variables.mask_json(&captured.json_or_text()),
+        })
+    }
+}
+
+/// Print the differences between two responses, returning how many there are
+pub fn display_comparison(left: &Side, right: &Side) -> usize {
+    println!("{} {}", "LEFT     :".blue().bold(), left.label.red());
+    println!("{} {}", "RIGHT    :".blue().bold(), right.label.green());
+
+    let mut differences = 0;
+    if left.status == right.status {
+        println!(
+            "{} {}",
+            "Status   :".blue().bold(),
+            left.status.to_string().green()
+        );
+    } else {
+        differences += 1;
+        println!(
+            "{} {} -> {}",
+            "Status   :".blue().bold(),
+            left.status.to_string().red(),
+            right.status.to_string().green()
+        );
+    }
+
+    // The Date header differs on every response
+    let header_map = |side: &Side| -> BTreeMap<String, String> {
+        let mut headers = BTreeMap::new();
+        for (name, value) in &side.headers {
+            let name = name.to_lowercase();
+            if name != "date" {
+                headers
+                    .entry(name)
+                    .and_modify(|values: &mut String| {
+                        values.push_str(", ");
+                        values.push_str(value);
+                    })
+                    .or_insert_with(|| value.clone());
+            }
+        }
+        headers
+    };
+    let (left_headers, right_headers) = (header_map(left), header_map(right));
+    println!("{}", "Headers  :".blue().bold());
+    let mut names: Vec<&String> = left_headers.keys().chain(right_headers.keys()).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        match (left_headers.get(name), right_headers.get(name)) {
+            (Some(left), Some(right)) if left == right => continue,
+            (Some(left), Some(right)) => println!(
+                "  {} {}: {} -> {}",
+                "~".yellow(),
+                name,
+                left.red(),
+                right.green()
+            ),
+            (Some(left), None) => println!("  {} {}: {}", "-".red(), name, left.red()),
+            (None, Some(right)) => println!("  {} {}: {}", "+".green(), name, right.green()),
+            (None, None) => continue,
+        }
+        differences += 1;
+    }
+
+    println!("{}", "Body     :".blue().bold());
+    let body_differences = snapshot::differences(&left.body, &right.body);
+    for difference in &body_differences {
+        println!("{}", difference.line());
+    }
+    differences += body_differences.len();
+    println!();
+
+    if differences == 0 {
+        println!("{} {}", "COMPARE  :".blue().bold(), "identical".green());
+    } else {
+        println!(
+            "{} {}",
+            "COMPARE  :".blue().bold(),
+            format!("{} differences", differences).red()
+        );
+    }
+    differences
+}
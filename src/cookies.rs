@@ -0,0 +1,85 @@
+use colored::Colorize;
+use cookie_store::{CookieExpiration, CookieStore};
+use reqwest_cookie_store::CookieStoreMutex;
+use std::fs::{remove_file, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::file_ops::{expand_tilde, write_atomic};
+
+/// The cookie jar lives next to `variable_dir`, e.g. `_variables` -> `_variables.cookies.json`
+pub fn cookie_jar_path(variable_dir: &str) -> PathBuf {
+    let dir = expand_tilde(variable_dir.trim_end_matches('/'));
+    PathBuf::from(format!("{}.cookies.json", dir))
+}
+
+/// Load the saved cookies, starting empty when there is no jar yet
+pub fn load_cookie_jar(path: &Path) -> io::Result<Arc<CookieStoreMutex>> {
+    let store = if path.exists() {
+        let reader = BufReader::new(File::open(path)?);
+        cookie_store::serde::json::load_all(reader)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+    } else {
+        CookieStore::default()
+    };
+
+    Ok(Arc::new(CookieStoreMutex::new(store)))
+}
+
+/// Save every cookie, session cookies included, so the next run reuses them
+pub fn save_cookie_jar(jar: &CookieStoreMutex, path: &Path) -> io::Result<()> {
+    let store = jar
+        .lock()
+        .map_err(|_| io::Error::other("Cookie jar lock is poisoned"))?;
+    let mut contents = Vec::new();
+    cookie_store::serde::json::save_incl_expired_and_nonpersistent(&store, &mut contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_atomic(path, &contents)
+}
+
+/// Print every cookie in the jar
+pub fn list_cookies(path: &Path) -> io::Result<()> {
+    let jar = load_cookie_jar(path)?;
+    let store = jar
+        .lock()
+        .map_err(|_| io::Error::other("Cookie jar lock is poisoned"))?;
+
+    println!(
+        "{} {}",
+        "JAR      :".blue().bold(),
+        path.display().to_string().yellow()
+    );
+    let mut count = 0;
+    for cookie in store.iter_unexpired() {
+        let expires = match &cookie.expires {
+            CookieExpiration::AtUtc(at) => at.to_string(),
+            CookieExpiration::SessionEnd => "session".to_string(),
+        };
+        println!(
+            "{}={} {} {} {}",
+            cookie.name().green(),
+            cookie.value().yellow(),
+            String::from(&cookie.domain).blue(),
+            cookie.path.as_ref().blue(),
+            expires.purple()
+        );
+        count += 1;
+    }
+    println!(
+        "{} {}",
+        "COOKIES  :".blue().bold(),
+        count.to_string().green()
+    );
+
+    Ok(())
+}
+
+/// Remove the jar file
+pub fn clear_cookies(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        remove_file(path)?;
+    }
+    println!("Cleared: {}", path.display().to_string().yellow().bold());
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use colored::Colorize;
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::io;
+use std::path::Path;
+
+use crate::file_ops::{expand_tilde, read_from_file};
+
+/// One row of a data file, each column becomes a `{{COLUMN}}` placeholder
+pub type DataRow = Vec<(String, String)>;
+
+/// The statuses of every tag run in one iteration, None when no response came back
+pub struct IterationResult {
+    pub row: usize,
+    pub statuses: Vec<(String, Option<StatusCode>)>,
+}
+
+impl IterationResult {
+    pub fn passed(&self) -> bool {
+        all_succeeded(&self.statuses)
+    }
+}
+
+/// Whether every tag got a 2xx response
+pub fn all_succeeded(statuses: &[(String, Option<StatusCode>)]) -> bool {
+    statuses
+        .iter()
+        .all(|(_, status)| status.is_some_and(|status| status.is_success()))
+}
+
+/// Load the rows of a `.csv` file (with a header line) or a `.json` array of objects
+pub fn load_data_rows(data_file: &str) -> io::Result<Vec<DataRow>> {
+    let full_path = expand_tilde(data_file);
+    let is_csv = Path::new(&full_path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+
+    if is_csv {
+        let mut reader = csv::Reader::from_path(&full_path)?;
+        let headers = reader.headers()?.clone();
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            rows.push(
+                headers
+                    .iter()
+                    .zip(record.iter())
+                    .map(|(name, value)| (name.trim().to_string(), value.to_string()))
+                    .collect(),
+            );
+        }
+        return Ok(rows);
+    }
+
+    let content = read_from_file(&full_path)?;
+    let data: Value = serde_json::from_str(&content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let items = data.as_array().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "A JSON data file must be an array of objects",
+        )
+    })?;
+
+    items
+        .iter()
+        .map(|item| {
+            let object = item.as_object().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Every item of a JSON data file must be an object",
+                )
+            })?;
+            Ok(object
+                .iter()
+                .map(|(name, value)| {
+                    let value = match value {
+                        Value::String(text) => text.clone(),
+                        other => other.to_string(),
+                    };
+                    (name.clone(), value)
+                })
+                .collect())
+        })
+        .collect()
+}
+
+/// Print one line per iteration and the pass/fail totals
+pub fn display_iteration_summary(results: &[IterationResult]) {
+    eprintln!("{}", "ITERATIONS:".blue().bold());
+    for result in results {
+        let statuses = result
+            .statuses
+            .iter()
+            .map(|(tag, status)| match status {
+                Some(status) => format!("{} {}", tag, status.as_u16()),
+                None => format!("{} no response", tag),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        let outcome = if result.passed() {
+            "PASS".green().bold()
+        } else {
+            "FAIL".red().bold()
+        };
+        eprintln!("#{:<4} {} {}", result.row, outcome, statuses);
+    }
+
+    let passed = results.iter().filter(|result| result.passed()).count();
+    eprintln!(
+        "{} {} passed, {} failed",
+        "TOTAL    :".blue().bold(),
+        passed.to_string().green(),
+        (results.len() - passed).to_string().red()
+    );
+}
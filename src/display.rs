@@ -0,0 +1,66 @@
+use colored::Colorize;
+use serde_json::Value;
+
+use crate::filter::OutputFormat;
+
+/// Print an error under an `Error :` label
+pub fn display_error(err: impl std::fmt::Display) {
+    eprintln!("{}", "Error : ".red().bold());
+    eprintln!("{}", err.to_string().red());
+}
+
+/// Print a JSON value indented, with its keys, strings, numbers, booleans and nulls colored
+pub fn display_colored_json(value: &Value, indent_level: usize) {
+    match value {
+        Value::Object(map) => {
+            print!("{}", "{".blue());
+            let len = map.len();
+            let iter = map.iter().enumerate();
+            for (i, (key, val)) in iter {
+                print!("\n{}", " ".repeat(indent_level + 4));
+                print!("\"{}\": ", key.blue()); // Key in blue with quotes
+                display_colored_json(val, indent_level + 4);
+                if i != len - 1 {
+                    print!(",");
+                }
+            }
+            print!("\n{}", " ".repeat(indent_level));
+            print!("{}", "}".blue());
+        }
+        Value::Array(arr) => {
+            print!("{}", "[".blue());
+            let len = arr.len();
+            let iter = arr.iter().enumerate();
+            for (i, val) in iter {
+                print!("\n{}", " ".repeat(indent_level + 4));
+                display_colored_json(val, indent_level + 4);
+                if i != len - 1 {
+                    print!(",");
+                }
+            }
+            print!("\n{}", " ".repeat(indent_level));
+            print!("{}", "]".blue());
+        }
+        Value::String(s) => print!("\"{}\"", s.yellow()),
+        Value::Number(num) => print!("{}", num.to_string().green()),
+        Value::Bool(b) => print!("{}", b.to_string().purple()),
+        Value::Null => print!("{}", "null".red()),
+    }
+}
+
+/// Print the values picked by `--filter`
+pub fn display_output(values: &[Value], format: OutputFormat) {
+    for value in values {
+        match (format, value) {
+            (OutputFormat::Pretty, value) => {
+                display_colored_json(value, 0);
+                println!();
+            }
+            (OutputFormat::Raw, Value::String(text)) => println!("{}", text),
+            (_, value) => println!("{}", value),
+        }
+    }
+    if format == OutputFormat::Pretty {
+        println!();
+    }
+}
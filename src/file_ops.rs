@@ -1,15 +1,19 @@
 use colored::Colorize;
 use serde_json::Value;
 use std::env;
-use std::fs::{create_dir_all, File};
+use std::fs::{self, create_dir_all, File, OpenOptions};
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 
+use crate::capture::{is_capture_source, split_mapping_shorthand, CapturedResponse};
+use crate::variables::VariableStore;
+
 // File mapping structure for directory mode
 #[derive(Debug)]
 struct FileMapping {
     filename: String,
     json_path: String,
+    persist: Option<bool>,
 }
 
 /// Expand tilde in paths to home directory
@@ -29,89 +33,206 @@ pub fn expand_tilde(path: &str) -> String {
     }
 }
 
-/// Write data to file(s) - handles both single file and directory modes
-pub fn write_to_file(
-    body_data: &Value,
-    variable_path: &Option<String>,
-    variable_structure: &Value,
-) -> io::Result<()> {
-    // If no path provided, skip writing
-    let path_str = match variable_path {
-        Some(path) => path,
-        None => return Ok(()),
-    };
-
-    // Extract the structure string from the Value
-    let structure_str = variable_structure.as_str().ok_or_else(|| {
-        io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "variable_structure is not a string",
-        )
-    })?;
-
-    let full_path = expand_tilde(path_str);
-    let path = Path::new(&full_path);
-
-    // Check if path is a directory
-    if path.is_dir() {
-        // Directory mode: Read all files and extract values by key
-        write_multiple_files(body_data, path, structure_str)
+/// A relative `file` of a collection: next to the collection when it is there,
+/// otherwise in the working directory like before
+pub fn resolve_relative(collection_dir: &Path, file: &str) -> String {
+    let expanded = expand_tilde(file);
+    if Path::new(&expanded).is_absolute() {
+        return expanded;
+    }
+    let next_to_collection = collection_dir.join(&expanded);
+    if next_to_collection.exists() {
+        next_to_collection.to_string_lossy().into_owned()
     } else {
-        // File mode: Write single file (original behavior)
-        write_single_file(body_data, path, structure_str)
+        expanded
     }
 }
 
-/// Write to a single file
-fn write_single_file(body_data: &Value, path: &Path, structure_str: &str) -> io::Result<()> {
-    let nested_value = get_nested_value(body_data, structure_str).unwrap();
-    let variable_value = nested_value.as_str().unwrap_or_default();
-
-    // Create directory if it doesn't exist
-    if let Some(parent) = path.parent() {
-        create_dir_all(parent)?;
+/// A tag or name turned into a file name that stays inside its directory:
+/// path separators and other unusual characters become `_`, and it never starts with a dot
+pub fn safe_file_name(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match safe.strip_prefix('.') {
+        Some(_) => format!("_{}", safe),
+        None if safe.is_empty() => "_".to_string(),
+        None => safe,
     }
+}
 
-    // Write to file
-    let mut file = File::create(path)?;
-    file.write_all(variable_value.as_bytes())?;
-    Ok(())
+/// The `.rest_api.lock` file of a directory, locked until it is dropped
+pub struct DirLock {
+    _file: File,
 }
 
-/// Write to multiple files/folders in a directory based on key mappings
-fn write_multiple_files(body_data: &Value, dir_path: &Path, structure_str: &str) -> io::Result<()> {
-    // 1. Ensure the root directory exists
-    create_dir_all(dir_path)?;
+/// Lock the directory of `path` against writers from parallel runs.
+/// Hold it around a read-modify-write, and write with `write_atomic_locked`.
+pub fn lock_for(path: &Path) -> io::Result<DirLock> {
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(parent_dir(path)?.join(".rest_api.lock"))?;
+    lock.lock()?;
+    Ok(DirLock { _file: lock })
+}
 
-    let mappings = parse_structure_mappings(structure_str);
+/// The directory of `path`, created when missing
+fn parent_dir(path: &Path) -> io::Result<&Path> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    create_dir_all(parent)?;
+    Ok(parent)
+}
 
-    for mapping in mappings {
-        // 2. Resolve the full path for this specific file/mapping
-        let file_path = dir_path.join(&mapping.filename);
+/// Write a file through a temp file and a rename, so readers never see a half-written value.
+/// The directory `.rest_api.lock` file serializes writers from parallel runs, and new files are 0600.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let lock = lock_for(path)?;
+    write_atomic_locked(path, contents, &lock)
+}
 
-        // 3. CRITICAL: Ensure the parent directory for this specific file exists
-        // This allows mapping.filename to be "subdir/sub-subdir/file.txt"
-        if let Some(parent) = file_path.parent() {
-            create_dir_all(parent)?;
+/// `write_atomic` for a caller already holding the lock of the directory
+pub fn write_atomic_locked(path: &Path, contents: &[u8], _lock: &DirLock) -> io::Result<()> {
+    let parent = parent_dir(path)?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = parent.join(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let result = (|| {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options.open(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Capture values from the response as variables for the rest of the run.
+/// A mapping (`{"ACCESS_TOKEN.txt": "data.access_token"}` or "FILE:path,FILE:path") captures
+/// one variable per entry, a single source captures `token_file` (variable_access_token_file).
+/// They are also saved in variable_dir when `persist` is set, or per entry with
+/// `{"ID.txt": {"from": "data.id", "persist": true}}`.
+pub fn write_to_file(
+    response: &CapturedResponse,
+    variable_structure: &Value,
+    token_file: &Option<String>,
+    persist: bool,
+    variables: &VariableStore,
+) -> io::Result<()> {
+    let mappings = match variable_structure {
+        Value::Null => return Ok(()),
+        Value::Object(_) => parse_structure_mappings(&variable_structure.to_string()),
+        Value::String(structure_str) if !is_capture_source(structure_str) => {
+            parse_structure_mappings(structure_str)
+        }
+        Value::String(json_path) => {
+            let filename = token_file.clone().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "A single source is saved to variable_access_token_file, which is not set",
+                )
+            })?;
+            vec![FileMapping {
+                filename,
+                json_path: json_path.clone(),
+                persist: None,
+            }]
         }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "req_variable_response_value must be a source or a FILE -> source mapping",
+            ))
+        }
+    };
+
+    write_mappings(response, &mappings, persist, variables)
+}
+
+/// Write every mapping that is found in the response, then report the missing ones together
+fn write_mappings(
+    response: &CapturedResponse,
+    mappings: &[FileMapping],
+    persist: bool,
+    variables: &VariableStore,
+) -> io::Result<()> {
+    let mut missing = Vec::new();
+
+    for mapping in mappings {
+        let variable_value = match response.capture(&mapping.json_path) {
+            Ok(Some(value)) => value,
+            result => {
+                let reason = match result {
+                    Err(err) => err.to_string(),
+                    _ => "not found".to_string(),
+                };
+                eprintln!(
+                    "{} {} (path: {}) {}",
+                    "Not found:".red().bold(),
+                    mapping.filename.yellow(),
+                    mapping.json_path.red(),
+                    reason
+                );
+                missing.push(format!("{} ({})", mapping.filename, mapping.json_path));
+                continue;
+            }
+        };
 
-        // 4. Extract data and write
-        let nested_value = get_nested_value(body_data, &mapping.json_path)
-            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "JSON path not found"))?;
-        
-        let variable_value = nested_value.as_str().unwrap_or_default();
+        let name = mapping
+            .filename
+            .strip_suffix(".txt")
+            .unwrap_or(&mapping.filename);
+        variables.remember(name, &variable_value);
 
-        let mut file = File::create(&file_path)?;
-        file.write_all(variable_value.as_bytes())?;
+        if !mapping.persist.unwrap_or(persist) {
+            eprintln!(
+                "Captured: {} (from path: {})",
+                name.yellow().bold(),
+                mapping.json_path.to_string().yellow()
+            );
+            continue;
+        }
+
+        // The directory is created if it doesn't exist, secret variables are encrypted
+        let file_path = variables.save_file(&mapping.filename, &variable_value)?;
 
-        println!(
+        eprintln!(
             "Written to: {} (from path: {})",
             file_path.display().to_string().yellow().bold(),
             mapping.json_path.to_string().yellow()
         );
     }
 
-    Ok(())
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "Nothing captured from the response for: {}",
+                missing.join(", ")
+            ),
+        ))
+    }
 }
 
 /// Parse structure string to extract filename -> JSON path mappings
@@ -126,6 +247,14 @@ fn parse_structure_mappings(structure_str: &str) -> Vec<FileMapping> {
                     mappings.push(FileMapping {
                         filename: filename.clone(),
                         json_path: json_path.to_string(),
+                        persist: None,
+                    });
+                } else if let Some(json_path) = json_path_value.get("from").and_then(Value::as_str)
+                {
+                    mappings.push(FileMapping {
+                        filename: filename.clone(),
+                        json_path: json_path.to_string(),
+                        persist: json_path_value.get("persist").and_then(Value::as_bool),
                     });
                 }
             }
@@ -134,12 +263,13 @@ fn parse_structure_mappings(structure_str: &str) -> Vec<FileMapping> {
     }
 
     // Fallback to key:value format
-    for pair in structure_str.split(',') {
+    for pair in split_mapping_shorthand(structure_str) {
         let parts: Vec<&str> = pair.splitn(2, ':').collect();
         if parts.len() == 2 {
             mappings.push(FileMapping {
                 filename: parts[0].trim().to_string(),
                 json_path: parts[1].trim().to_string(),
+                persist: None,
             });
         }
     }
@@ -157,10 +287,86 @@ pub fn read_from_file(file_path: &str) -> io::Result<String> {
 }
 
 /// This would typically be imported from another module
-fn get_nested_value<'a>(data: &'a Value, path: &'a str) -> Option<&'a Value> {
+pub fn get_nested_value<'a>(data: &'a Value, path: &'a str) -> Option<&'a Value> {
     let mut current = data;
     for key in path.split('.') {
-        current = current.get(key)?;
+        current = match (current, key.parse::<usize>()) {
+            (Value::Array(items), Ok(index)) => items.get(index)?,
+            _ => current.get(key)?,
+        };
     }
     Some(current)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safe_file_names_stay_in_their_directory() {
+        assert_eq!(safe_file_name("get_users_42"), "get_users_42");
+        assert_eq!(safe_file_name("login.3"), "login.3");
+        assert_eq!(safe_file_name("../../etc/passwd"), "_.._.._etc_passwd");
+        assert_eq!(safe_file_name(".."), "_..");
+        assert_eq!(safe_file_name("a/b\\c"), "a_b_c");
+        assert_eq!(safe_file_name(""), "_");
+    }
+
+    #[test]
+    fn relative_files_are_found_next_to_the_collection() {
+        let dir = env::temp_dir().join(format!("rest_api_relative_{}", std::process::id()));
+        write_atomic(&dir.join("bodies/user.json"), b"{}").unwrap();
+
+        assert_eq!(
+            resolve_relative(&dir, "bodies/user.json"),
+            dir.join("bodies/user.json").to_string_lossy()
+        );
+        assert_eq!(resolve_relative(&dir, "missing.json"), "missing.json");
+        assert_eq!(resolve_relative(&dir, "/abs/user.json"), "/abs/user.json");
+    }
+
+    #[test]
+    fn atomic_writes_replace_the_file_without_leftovers() {
+        let dir = env::temp_dir().join(format!("rest_api_atomic_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("nested/TOKEN.txt");
+
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+
+        let mut names: Vec<String> = fs::read_dir(dir.join("nested"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, [".rest_api.lock", "TOKEN.txt"]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn writers_wait_for_the_directory_lock() {
+        let dir = env::temp_dir().join(format!("rest_api_lock_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("ID.txt");
+
+        let lock = lock_for(&path).unwrap();
+        let writer = {
+            let path = path.clone();
+            std::thread::spawn(move || write_atomic(&path, b"from the writer"))
+        };
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(!writer.is_finished());
+        write_atomic_locked(&path, b"from the lock holder", &lock).unwrap();
+        drop(lock);
+
+        writer.join().unwrap().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "from the writer");
+    }
+}
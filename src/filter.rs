@@ -0,0 +1,1017 @@
+use serde_json::{Map, Number, Value};
+use std::cmp::Ordering;
+
+/// How `--filter` results are printed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Indented and colored, after the title, URL and status
+    #[default]
+    Pretty,
+    /// One JSON value per line, nothing else
+    Compact,
+    /// Like `Compact`, but strings without their quotes
+    Raw,
+}
+
+/// A jq-like expression applied to the response body, e.g. `.data[] | select(.age > 30) | .name`.
+///
+/// Supported: `.`, `..`, `.a.b`, `."a b"`, `.[0]`, `.[-1]`, `.[2:5]`, `.[]`, `|`, `,`,
+/// `==` `!=` `<` `<=` `>` `>=` `and` `or`, literals, `[...]`, `{a, b: .c}`, and
+/// `length`, `keys`, `select(f)`, `map(f)`, `has(k)`, `first`, `last`, `not`, `type`,
+/// `sort`, `sort_by(f)`, `add`, `empty`.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    expr: Expr,
+}
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Filter, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.pipe()?;
+        match parser.peek() {
+            None => Ok(Filter { expr }),
+            Some(token) => Err(format!("Unexpected {:?} in filter '{}'", token, source)),
+        }
+    }
+
+    /// Every value the expression produces for `input`
+    pub fn apply(&self, input: &Value) -> Result<Vec<Value>, String> {
+        eval(&self.expr, input)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Dot,
+    DotDot,
+    /// `.name`
+    Field(String),
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Op(&'static str),
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Pipe,
+    Comma,
+    Colon,
+    Semicolon,
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '.' if next == Some('.') => {
+                tokens.push(Token::DotDot);
+                i += 2;
+            }
+            '.' if next.is_some_and(|n| n.is_ascii_alphabetic() || n == '_') => {
+                let start = i + 1;
+                i = start;
+                while i < chars.len() && is_ident_char(chars[i]) {
+                    i += 1;
+                }
+                tokens.push(Token::Field(chars[start..i].iter().collect()));
+            }
+            '.' => {
+                tokens.push(Token::Dot);
+                i += 1;
+            }
+            '"' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err("Unterminated string in filter".to_string()),
+                        Some('"') => break,
+                        Some('\\') => {
+                            text.push(match chars.get(i + 1) {
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some(other) => *other,
+                                None => return Err("Unterminated string in filter".to_string()),
+                            });
+                            i += 2;
+                        }
+                        Some(other) => {
+                            text.push(*other);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(Token::Str(text));
+                i += 1;
+            }
+            _ if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = text
+                    .parse()
+                    .map_err(|_| format!("Invalid number '{}' in filter", text))?;
+                tokens.push(Token::Num(number));
+            }
+            // A minus sign only starts a negative number, there is no arithmetic
+            '-' if next.is_some_and(|n| n.is_ascii_digit()) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = text
+                    .parse()
+                    .map_err(|_| format!("Invalid number '{}' in filter", text))?;
+                tokens.push(Token::Num(number));
+            }
+            _ if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && is_ident_char(chars[i]) {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            '=' | '!' | '<' | '>' => {
+                let op = match (c, next) {
+                    ('=', Some('=')) => "==",
+                    ('!', Some('=')) => "!=",
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    _ => return Err(format!("Unexpected '{}' in filter", c)),
+                };
+                tokens.push(Token::Op(op));
+                i += op.len();
+            }
+            _ => {
+                tokens.push(match c {
+                    '[' => Token::LBracket,
+                    ']' => Token::RBracket,
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    '{' => Token::LBrace,
+                    '}' => Token::RBrace,
+                    '|' => Token::Pipe,
+                    ',' => Token::Comma,
+                    ':' => Token::Colon,
+                    ';' => Token::Semicolon,
+                    _ => return Err(format!("Unexpected '{}' in filter", c)),
+                });
+                i += 1;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Identity,
+    Recurse,
+    Literal(Value),
+    /// `target[index]`, the index is evaluated against the input, not the target
+    Index(Box<Expr>, Box<Expr>),
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+    Iterate(Box<Expr>),
+    Pipe(Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+    Compare(&'static str, Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Array(Option<Box<Expr>>),
+    Object(Vec<(Expr, Expr)>),
+    Call(String, Vec<Expr>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match self.next() {
+            Some(found) if found == token => Ok(()),
+            Some(found) => Err(format!("Expected {:?} in filter, found {:?}", token, found)),
+            None => Err(format!("Expected {:?} at the end of the filter", token)),
+        }
+    }
+
+    fn pipe(&mut self) -> Result<Expr, String> {
+        let mut expr = self.comma()?;
+        while self.eat(&Token::Pipe) {
+            expr = Expr::Pipe(Box::new(expr), Box::new(self.comma()?));
+        }
+        Ok(expr)
+    }
+
+    fn comma(&mut self) -> Result<Expr, String> {
+        let mut expr = self.or()?;
+        while self.eat(&Token::Comma) {
+            expr = Expr::Comma(Box::new(expr), Box::new(self.or()?));
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Ident("or".to_string())) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.compare()?;
+        while self.eat(&Token::Ident("and".to_string())) {
+            expr = Expr::And(Box::new(expr), Box::new(self.compare()?));
+        }
+        Ok(expr)
+    }
+
+    fn compare(&mut self) -> Result<Expr, String> {
+        let left = self.postfix()?;
+        match self.peek() {
+            Some(Token::Op(op)) => {
+                let op = *op;
+                self.pos += 1;
+                Ok(Expr::Compare(op, Box::new(left), Box::new(self.postfix()?)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+        loop {
+            match self.peek() {
+                Some(Token::Field(name)) => {
+                    let name = name.clone();
+                    self.pos += 1;
+                    expr =
+                        Expr::Index(Box::new(expr), Box::new(Expr::Literal(Value::String(name))));
+                }
+                Some(Token::LBracket) => {
+                    self.pos += 1;
+                    expr = self.bracket(expr)?;
+                }
+                Some(Token::Dot) => match self.tokens.get(self.pos + 1) {
+                    Some(Token::LBracket) => {
+                        self.pos += 2;
+                        expr = self.bracket(expr)?;
+                    }
+                    Some(Token::Str(name)) => {
+                        let name = name.clone();
+                        self.pos += 2;
+                        expr = Expr::Index(
+                            Box::new(expr),
+                            Box::new(Expr::Literal(Value::String(name))),
+                        );
+                    }
+                    _ => return Ok(expr),
+                },
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    /// After a `[`: `[]`, `[index]`, `[from:to]`
+    fn bracket(&mut self, target: Expr) -> Result<Expr, String> {
+        if self.eat(&Token::RBracket) {
+            return Ok(Expr::Iterate(Box::new(target)));
+        }
+        if self.eat(&Token::Colon) {
+            let to = self.pipe()?;
+            self.expect(Token::RBracket)?;
+            return Ok(Expr::Slice(Box::new(target), None, Some(Box::new(to))));
+        }
+
+        let index = self.pipe()?;
+        if self.eat(&Token::Colon) {
+            let to = if self.peek() == Some(&Token::RBracket) {
+                None
+            } else {
+                Some(Box::new(self.pipe()?))
+            };
+            self.expect(Token::RBracket)?;
+            return Ok(Expr::Slice(Box::new(target), Some(Box::new(index)), to));
+        }
+        self.expect(Token::RBracket)?;
+        Ok(Expr::Index(Box::new(target), Box::new(index)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Dot) => match self.peek() {
+                Some(Token::LBracket) => {
+                    self.pos += 1;
+                    self.bracket(Expr::Identity)
+                }
+                Some(Token::Str(name)) => {
+                    let name = name.clone();
+                    self.pos += 1;
+                    Ok(Expr::Index(
+                        Box::new(Expr::Identity),
+                        Box::new(Expr::Literal(Value::String(name))),
+                    ))
+                }
+                _ => Ok(Expr::Identity),
+            },
+            Some(Token::DotDot) => Ok(Expr::Recurse),
+            Some(Token::Field(name)) => Ok(Expr::Index(
+                Box::new(Expr::Identity),
+                Box::new(Expr::Literal(Value::String(name))),
+            )),
+            Some(Token::Num(number)) => Ok(Expr::Literal(number_value(number))),
+            Some(Token::Str(text)) => Ok(Expr::Literal(Value::String(text))),
+            Some(Token::LParen) => {
+                let expr = self.pipe()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::LBracket) => {
+                if self.eat(&Token::RBracket) {
+                    return Ok(Expr::Array(None));
+                }
+                let expr = self.pipe()?;
+                self.expect(Token::RBracket)?;
+                Ok(Expr::Array(Some(Box::new(expr))))
+            }
+            Some(Token::LBrace) => self.object(),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                _ => {
+                    let mut args = Vec::new();
+                    if self.eat(&Token::LParen) {
+                        args.push(self.pipe()?);
+                        while self.eat(&Token::Semicolon) {
+                            args.push(self.pipe()?);
+                        }
+                        self.expect(Token::RParen)?;
+                    }
+                    Ok(Expr::Call(name, args))
+                }
+            },
+            Some(token) => Err(format!("Unexpected {:?} in filter", token)),
+            None => Err("The filter ends too early".to_string()),
+        }
+    }
+
+    /// After a `{`: `{a, "b c": .x, (.k): .v}`
+    fn object(&mut self) -> Result<Expr, String> {
+        let mut entries = Vec::new();
+        if self.eat(&Token::RBrace) {
+            return Ok(Expr::Object(entries));
+        }
+
+        loop {
+            let (key, shorthand) = match self.next() {
+                Some(Token::Ident(name)) => (Value::String(name.clone()), Some(name)),
+                Some(Token::Str(name)) => (Value::String(name.clone()), Some(name)),
+                Some(Token::LParen) => {
+                    let key = self.pipe()?;
+                    self.expect(Token::RParen)?;
+                    self.expect(Token::Colon)?;
+                    entries.push((key, self.or()?));
+                    if self.eat(&Token::Comma) {
+                        continue;
+                    }
+                    self.expect(Token::RBrace)?;
+                    return Ok(Expr::Object(entries));
+                }
+                Some(token) => return Err(format!("Unexpected {:?} in an object", token)),
+                None => return Err("The filter ends inside an object".to_string()),
+            };
+
+            let value = if self.eat(&Token::Colon) {
+                self.or()?
+            } else {
+                // `{name}` is `{name: .name}`
+                let name = shorthand.unwrap_or_default();
+                Expr::Index(
+                    Box::new(Expr::Identity),
+                    Box::new(Expr::Literal(Value::String(name))),
+                )
+            };
+            entries.push((Expr::Literal(key), value));
+
+            if !self.eat(&Token::Comma) {
+                self.expect(Token::RBrace)?;
+                return Ok(Expr::Object(entries));
+            }
+        }
+    }
+}
+
+/// Whole numbers stay integers, so `length` prints `3` and not `3.0`
+fn number_value(number: f64) -> Value {
+    if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+        Value::from(number as i64)
+    } else {
+        Number::from_f64(number).map_or(Value::Null, Value::Number)
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
+
+/// jq ordering: null < false < true < numbers < strings < arrays < objects
+fn compare_values(left: &Value, right: &Value) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Null => 0,
+            Value::Bool(false) => 1,
+            Value::Bool(true) => 2,
+            Value::Number(_) => 3,
+            Value::String(_) => 4,
+            Value::Array(_) => 5,
+            Value::Object(_) => 6,
+        }
+    }
+
+    match (left, right) {
+        (Value::Number(left), Value::Number(right)) => {
+            let (left, right) = (left.as_f64().unwrap_or(0.0), right.as_f64().unwrap_or(0.0));
+            left.partial_cmp(&right).unwrap_or(Ordering::Equal)
+        }
+        (Value::String(left), Value::String(right)) => left.cmp(right),
+        (Value::Array(left), Value::Array(right)) => {
+            for (left, right) in left.iter().zip(right) {
+                let ordering = compare_values(left, right);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            left.len().cmp(&right.len())
+        }
+        (Value::Object(left), Value::Object(right)) => {
+            let mut left_keys: Vec<&String> = left.keys().collect();
+            let mut right_keys: Vec<&String> = right.keys().collect();
+            left_keys.sort();
+            right_keys.sort();
+            left_keys.cmp(&right_keys).then_with(|| {
+                left_keys
+                    .iter()
+                    .map(|key| compare_values(&left[*key], &right[*key]))
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal)
+            })
+        }
+        (left, right) => rank(left).cmp(&rank(right)),
+    }
+}
+
+fn index_value(target: &Value, index: &Value) -> Result<Value, String> {
+    match (target, index) {
+        (Value::Null, _) => Ok(Value::Null),
+        (Value::Object(map), Value::String(key)) => {
+            Ok(map.get(key).cloned().unwrap_or(Value::Null))
+        }
+        (Value::Array(items), Value::Number(number)) => {
+            let index = number.as_f64().unwrap_or(0.0) as i64;
+            let index = if index < 0 {
+                items.len() as i64 + index
+            } else {
+                index
+            };
+            Ok(usize::try_from(index)
+                .ok()
+                .and_then(|index| items.get(index))
+                .cloned()
+                .unwrap_or(Value::Null))
+        }
+        (target, index) => Err(format!(
+            "Cannot index {} with {}",
+            type_name(target),
+            match index {
+                Value::String(key) => format!("\"{}\"", key),
+                other => type_name(other).to_string(),
+            }
+        )),
+    }
+}
+
+fn slice_value(target: &Value, from: Option<&Value>, to: Option<&Value>) -> Result<Value, String> {
+    let bound = |value: Option<&Value>, len: usize, default: usize| -> Result<usize, String> {
+        match value {
+            None | Some(Value::Null) => Ok(default),
+            Some(Value::Number(number)) => {
+                let index = number.as_f64().unwrap_or(0.0) as i64;
+                let index = if index < 0 { len as i64 + index } else { index };
+                Ok(index.clamp(0, len as i64) as usize)
+            }
+            Some(other) => Err(format!("Cannot slice with {}", type_name(other))),
+        }
+    };
+
+    match target {
+        Value::Null => Ok(Value::Null),
+        Value::Array(items) => {
+            let from = bound(from, items.len(), 0)?;
+            let to = bound(to, items.len(), items.len())?.max(from);
+            Ok(Value::Array(items[from..to].to_vec()))
+        }
+        Value::String(text) => {
+            let chars: Vec<char> = text.chars().collect();
+            let from = bound(from, chars.len(), 0)?;
+            let to = bound(to, chars.len(), chars.len())?.max(from);
+            Ok(Value::String(chars[from..to].iter().collect()))
+        }
+        other => Err(format!("Cannot slice {}", type_name(other))),
+    }
+}
+
+fn iterate_value(target: &Value) -> Result<Vec<Value>, String> {
+    match target {
+        Value::Array(items) => Ok(items.clone()),
+        Value::Object(map) => Ok(map.values().cloned().collect()),
+        other => Err(format!("Cannot iterate over {}", type_name(other))),
+    }
+}
+
+/// Every value inside `value`, itself first
+fn recurse_value(value: &Value, values: &mut Vec<Value>) {
+    values.push(value.clone());
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| recurse_value(item, values)),
+        Value::Object(map) => map.values().for_each(|item| recurse_value(item, values)),
+        _ => {}
+    }
+}
+
+fn add_values(left: Value, right: &Value) -> Result<Value, String> {
+    match (left, right) {
+        (Value::Null, right) => Ok(right.clone()),
+        (left, Value::Null) => Ok(left),
+        (Value::Number(left), Value::Number(right)) => Ok(number_value(
+            left.as_f64().unwrap_or(0.0) + right.as_f64().unwrap_or(0.0),
+        )),
+        (Value::String(left), Value::String(right)) => Ok(Value::String(left + right)),
+        (Value::Array(mut left), Value::Array(right)) => {
+            left.extend(right.iter().cloned());
+            Ok(Value::Array(left))
+        }
+        (Value::Object(mut left), Value::Object(right)) => {
+            left.extend(
+                right
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone())),
+            );
+            Ok(Value::Object(left))
+        }
+        (left, right) => Err(format!(
+            "Cannot add {} and {}",
+            type_name(&left),
+            type_name(right)
+        )),
+    }
+}
+
+fn eval(expr: &Expr, input: &Value) -> Result<Vec<Value>, String> {
+    match expr {
+        Expr::Identity => Ok(vec![input.clone()]),
+        Expr::Recurse => {
+            let mut values = Vec::new();
+            recurse_value(input, &mut values);
+            Ok(values)
+        }
+        Expr::Literal(value) => Ok(vec![value.clone()]),
+        Expr::Index(target, index) => {
+            let mut values = Vec::new();
+            for target in eval(target, input)? {
+                for index in eval(index, input)? {
+                    values.push(index_value(&target, &index)?);
+                }
+            }
+            Ok(values)
+        }
+        Expr::Slice(target, from, to) => {
+            let bounds = |bound: &Option<Box<Expr>>| -> Result<Option<Value>, String> {
+                match bound {
+                    Some(bound) => Ok(eval(bound, input)?.into_iter().next()),
+                    None => Ok(None),
+                }
+            };
+            let (from, to) = (bounds(from)?, bounds(to)?);
+            eval(target, input)?
+                .iter()
+                .map(|target| slice_value(target, from.as_ref(), to.as_ref()))
+                .collect()
+        }
+        Expr::Iterate(target) => {
+            let mut values = Vec::new();
+            for target in eval(target, input)? {
+                values.extend(iterate_value(&target)?);
+            }
+            Ok(values)
+        }
+        Expr::Pipe(left, right) => {
+            let mut values = Vec::new();
+            for value in eval(left, input)? {
+                values.extend(eval(right, &value)?);
+            }
+            Ok(values)
+        }
+        Expr::Comma(left, right) => {
+            let mut values = eval(left, input)?;
+            values.extend(eval(right, input)?);
+            Ok(values)
+        }
+        Expr::Compare(op, left, right) => {
+            let mut values = Vec::new();
+            for right in eval(right, input)? {
+                for left in eval(left, input)? {
+                    let ordering = compare_values(&left, &right);
+                    values.push(Value::Bool(match *op {
+                        "==" => ordering == Ordering::Equal,
+                        "!=" => ordering != Ordering::Equal,
+                        "<" => ordering == Ordering::Less,
+                        "<=" => ordering != Ordering::Greater,
+                        ">" => ordering == Ordering::Greater,
+                        _ => ordering != Ordering::Less,
+                    }));
+                }
+            }
+            Ok(values)
+        }
+        Expr::And(left, right) | Expr::Or(left, right) => {
+            let is_and = matches!(expr, Expr::And(..));
+            let mut values = Vec::new();
+            for left in eval(left, input)? {
+                // Short-circuit like jq: `false and ...` is false, `true or ...` is true
+                if truthy(&left) != is_and {
+                    values.push(Value::Bool(!is_and));
+                    continue;
+                }
+                for right in eval(right, input)? {
+                    values.push(Value::Bool(truthy(&right)));
+                }
+            }
+            Ok(values)
+        }
+        Expr::Array(inner) => Ok(vec![Value::Array(match inner {
+            Some(inner) => eval(inner, input)?,
+            None => Vec::new(),
+        })]),
+        Expr::Object(entries) => {
+            // Every combination of the key and value outputs, like jq
+            let mut objects = vec![Map::new()];
+            for (key, value) in entries {
+                let mut next = Vec::new();
+                for key in eval(key, input)? {
+                    let Value::String(key) = key else {
+                        return Err(format!(
+                            "Object keys must be strings, not {}",
+                            type_name(&key)
+                        ));
+                    };
+                    for value in eval(value, input)? {
+                        for object in &objects {
+                            let mut object = object.clone();
+                            object.insert(key.clone(), value.clone());
+                            next.push(object);
+                        }
+                    }
+                }
+                objects = next;
+            }
+            Ok(objects.into_iter().map(Value::Object).collect())
+        }
+        Expr::Call(name, args) => call(name, args, input),
+    }
+}
+
+fn call(name: &str, args: &[Expr], input: &Value) -> Result<Vec<Value>, String> {
+    let arity = |count: usize| -> Result<(), String> {
+        if args.len() == count {
+            Ok(())
+        } else {
+            Err(format!("{}/{} is not a filter function", name, args.len()))
+        }
+    };
+
+    match name {
+        "empty" => arity(0).map(|_| Vec::new()),
+        "not" => arity(0).map(|_| vec![Value::Bool(!truthy(input))]),
+        "type" => arity(0).map(|_| vec![Value::String(type_name(input).to_string())]),
+        "length" => {
+            arity(0)?;
+            let length = match input {
+                Value::Null => Value::from(0),
+                Value::Number(number) => number_value(number.as_f64().unwrap_or(0.0).abs()),
+                Value::String(text) => Value::from(text.chars().count()),
+                Value::Array(items) => Value::from(items.len()),
+                Value::Object(map) => Value::from(map.len()),
+                Value::Bool(_) => return Err("boolean has no length".to_string()),
+            };
+            Ok(vec![length])
+        }
+        "keys" => {
+            arity(0)?;
+            match input {
+                Value::Object(map) => {
+                    let mut keys: Vec<&String> = map.keys().collect();
+                    keys.sort();
+                    Ok(vec![Value::Array(
+                        keys.into_iter()
+                            .map(|key| Value::String(key.clone()))
+                            .collect(),
+                    )])
+                }
+                Value::Array(items) => Ok(vec![Value::Array(
+                    (0..items.len()).map(Value::from).collect(),
+                )]),
+                other => Err(format!("{} has no keys", type_name(other))),
+            }
+        }
+        "has" => {
+            arity(1)?;
+            eval(&args[0], input)?
+                .iter()
+                .map(|key| match (input, key) {
+                    (Value::Object(map), Value::String(key)) => {
+                        Ok(Value::Bool(map.contains_key(key)))
+                    }
+                    (Value::Array(items), Value::Number(index)) => {
+                        Ok(Value::Bool(index.as_f64().is_some_and(|index| {
+                            index >= 0.0 && (index as usize) < items.len()
+                        })))
+                    }
+                    (input, key) => Err(format!(
+                        "Cannot check whether {} has a {} key",
+                        type_name(input),
+                        type_name(key)
+                    )),
+                })
+                .collect()
+        }
+        "select" => {
+            arity(1)?;
+            Ok(eval(&args[0], input)?
+                .iter()
+                .filter(|value| truthy(value))
+                .map(|_| input.clone())
+                .collect())
+        }
+        "map" => {
+            arity(1)?;
+            let mut values = Vec::new();
+            for item in iterate_value(input)? {
+                values.extend(eval(&args[0], &item)?);
+            }
+            Ok(vec![Value::Array(values)])
+        }
+        "first" | "last" => {
+            let values = match args {
+                [] => vec![index_value(
+                    input,
+                    &Value::from(if name == "first" { 0 } else { -1 }),
+                )?],
+                [arg] => eval(arg, input)?,
+                _ => return arity(1).map(|_| Vec::new()),
+            };
+            let value = if name == "first" {
+                values.into_iter().next()
+            } else {
+                values.into_iter().last()
+            };
+            Ok(value.into_iter().collect())
+        }
+        "sort" | "sort_by" => {
+            arity(if name == "sort" { 0 } else { 1 })?;
+            let Value::Array(items) = input else {
+                return Err(format!("Cannot sort {}", type_name(input)));
+            };
+            let mut keyed = Vec::new();
+            for item in items {
+                let key = match args.first() {
+                    Some(arg) => Value::Array(eval(arg, item)?),
+                    None => item.clone(),
+                };
+                keyed.push((key, item.clone()));
+            }
+            keyed.sort_by(|(left, _), (right, _)| compare_values(left, right));
+            Ok(vec![Value::Array(
+                keyed.into_iter().map(|(_, item)| item).collect(),
+            )])
+        }
+        "add" => {
+            arity(0)?;
+            let mut sum = Value::Null;
+            for item in iterate_value(input)? {
+                sum = add_values(sum, &item)?;
+            }
+            Ok(vec![sum])
+        }
+        _ => Err(format!("{}/{} is not a filter function", name, args.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn run(source: &str, input: Value) -> Result<Vec<Value>, String> {
+        Filter::parse(source)?.apply(&input)
+    }
+
+    fn users() -> Value {
+        json!({"data": [
+            {"id": 1, "name": "ada", "age": 36, "active": true},
+            {"id": 2, "name": "bob", "age": 25, "active": false},
+            {"id": 3, "name": "cy", "age": 41, "active": true}
+        ]})
+    }
+
+    #[test]
+    fn tokenize_splits_fields_numbers_strings_and_operators() {
+        assert_eq!(
+            tokenize(r#".data[-1] | .name != "a\"b" , .. >= 1.5"#).unwrap(),
+            vec![
+                Token::Field("data".to_string()),
+                Token::LBracket,
+                Token::Num(-1.0),
+                Token::RBracket,
+                Token::Pipe,
+                Token::Field("name".to_string()),
+                Token::Op("!="),
+                Token::Str("a\"b".to_string()),
+                Token::Comma,
+                Token::DotDot,
+                Token::Op(">="),
+                Token::Num(1.5),
+            ]
+        );
+        assert_eq!(
+            tokenize(r#""open"#).unwrap_err(),
+            "Unterminated string in filter"
+        );
+        assert_eq!(tokenize(".a = 1").unwrap_err(), "Unexpected '=' in filter");
+        assert_eq!(tokenize(".a + 1").unwrap_err(), "Unexpected '+' in filter");
+    }
+
+    #[test]
+    fn pipe_binds_looser_than_comma_and_comma_looser_than_and_or() {
+        let input = json!({"a": true, "b": false, "c": {"x": 1}});
+        // `.c, .a | type` is `(.c, .a) | type`
+        assert_eq!(
+            run(".c, .a | type", input.clone()).unwrap(),
+            vec![json!("object"), json!("boolean")]
+        );
+        // `.a, .b and .a` is `.a, (.b and .a)`
+        assert_eq!(
+            run(".a, .b and .a", input.clone()).unwrap(),
+            vec![json!(true), json!(false)]
+        );
+        // `and` binds tighter than `or`
+        assert_eq!(
+            run(".a or .b and .b", input.clone()).unwrap(),
+            vec![json!(true)]
+        );
+        assert_eq!(run(".b and .a or .a", input).unwrap(), vec![json!(true)]);
+    }
+
+    #[test]
+    fn negative_indices_and_slices_count_from_the_end() {
+        let input = json!([10, 20, 30, 40]);
+        assert_eq!(run(".[-1]", input.clone()).unwrap(), vec![json!(40)]);
+        assert_eq!(run(".[-5]", input.clone()).unwrap(), vec![json!(null)]);
+        assert_eq!(run(".[1:3]", input.clone()).unwrap(), vec![json!([20, 30])]);
+        assert_eq!(run(".[-2:]", input.clone()).unwrap(), vec![json!([30, 40])]);
+        assert_eq!(run(".[:-3]", input.clone()).unwrap(), vec![json!([10])]);
+        assert_eq!(run(".[3:1]", input).unwrap(), vec![json!([])]);
+        assert_eq!(run(".[1:-1]", json!("abcd")).unwrap(), vec![json!("bc")]);
+    }
+
+    #[test]
+    fn object_shorthand_takes_the_field_of_the_same_name() {
+        assert_eq!(
+            run(".data[0] | {id, name, \"years\": .age}", users()).unwrap(),
+            vec![json!({"id": 1, "name": "ada", "years": 36})]
+        );
+        assert_eq!(
+            run("{(.data[0].name): .data[0].id}", users()).unwrap(),
+            vec![json!({"ada": 1})]
+        );
+    }
+
+    #[test]
+    fn functions_pick_map_and_sort_values() {
+        assert_eq!(
+            run("[.data[] | select(.active) | .name]", users()).unwrap(),
+            vec![json!(["ada", "cy"])]
+        );
+        assert_eq!(
+            run(".data | map(.age) | add", users()).unwrap(),
+            vec![json!(102)]
+        );
+        assert_eq!(
+            run(".data | sort_by(.age) | map(.id)", users()).unwrap(),
+            vec![json!([2, 1, 3])]
+        );
+        assert_eq!(
+            run(".data[0] | keys", users()).unwrap(),
+            vec![json!(["active", "age", "id", "name"])]
+        );
+        assert_eq!(run(".data | length", users()).unwrap(), vec![json!(3)]);
+    }
+
+    #[test]
+    fn functions_report_invalid_input() {
+        assert_eq!(
+            run(".data | select(.age > 30)", users()).unwrap_err(),
+            "Cannot index array with \"age\""
+        );
+        assert_eq!(
+            run(".data[0].id | map(.)", users()).unwrap_err(),
+            "Cannot iterate over number"
+        );
+        assert_eq!(
+            run(".data[0].active | length", users()).unwrap_err(),
+            "boolean has no length"
+        );
+        assert_eq!(
+            run(".data[0].name | keys", users()).unwrap_err(),
+            "string has no keys"
+        );
+        assert_eq!(
+            run(".data[0] | sort_by(.id)", users()).unwrap_err(),
+            "Cannot sort object"
+        );
+        assert_eq!(
+            run("[.data[0].id, .data[0].name] | add", users()).unwrap_err(),
+            "Cannot add number and string"
+        );
+        assert_eq!(
+            run("select(.a; .b)", users()).unwrap_err(),
+            "select/2 is not a filter function"
+        );
+        assert_eq!(
+            run("bogus", users()).unwrap_err(),
+            "bogus/0 is not a filter function"
+        );
+    }
+
+    #[test]
+    fn incomplete_filters_do_not_parse() {
+        assert_eq!(
+            Filter::parse(".data |").err().unwrap(),
+            "The filter ends too early"
+        );
+        assert!(Filter::parse(".data[").is_err());
+        assert!(Filter::parse("{id").is_err());
+    }
+}
@@ -0,0 +1,471 @@
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use colored::Colorize;
+use reqwest::header::HeaderMap;
+use reqwest_cookie_store::CookieStoreMutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, remove_file};
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::capture::CapturedResponse;
+use crate::collection::{AppMainRequest, RequestData};
+use crate::display::{display_colored_json, display_error};
+use crate::file_ops::{expand_tilde, lock_for, write_atomic_locked};
+use crate::request::{send_stored_request, RequestOverrides, ResolvedRequest};
+use crate::secrets::MASK;
+use crate::variables::VariableStore;
+
+fn default_limit() -> usize {
+    500
+}
+
+/// How much history a collection keeps, set with `"history": { "limit": 500 }`.
+/// A limit of 0 records nothing.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HistoryConfig {
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            limit: default_limit(),
+        }
+    }
+}
+
+/// The history lives next to `variable_dir`, e.g. `_variables` -> `_variables.history.jsonl`
+pub fn history_path(variable_dir: &str) -> PathBuf {
+    let dir = expand_tilde(variable_dir.trim_end_matches('/'));
+    PathBuf::from(format!("{}.history.jsonl", dir))
+}
+
+/// The request as it was sent, before auth was applied
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct HistoryRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Value>,
+}
+
+/// One execution of a request. Secret values are stored masked.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    /// RFC 3339, in UTC
+    pub timestamp: String,
+    /// The base_url, which tells staging from production
+    pub environment: String,
+    pub tag: String,
+    pub title: String,
+    /// The `--query` and `--path` values the request was resolved with
+    pub query: Vec<(String, String)>,
+    pub path: Vec<(String, String)>,
+    pub request: HistoryRequest,
+    /// None when no response came back, `error` says why
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub response_headers: Vec<(String, String)>,
+    pub response_body: String,
+    pub duration_ms: u64,
+}
+
+/// Header names and values, secret values masked
+fn masked_headers(headers: &HeaderMap, variables: &VariableStore) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| {
+            Some((name.to_string(), variables.mask_str(value.to_str().ok()?)))
+        })
+        .collect()
+}
+
+impl HistoryEntry {
+    /// An entry for a request sent now, without its response yet
+    pub fn new(
+        app_main_request: &AppMainRequest,
+        request: &RequestData,
+        resolved: &ResolvedRequest,
+        overrides: &RequestOverrides,
+        variables: &VariableStore,
+    ) -> HistoryEntry {
+        HistoryEntry {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            environment: variables.mask_str(&app_main_request.base_url),
+            tag: request.req_tag.clone(),
+            title: request.req_title.clone(),
+            query: overrides.query.clone(),
+            path: overrides.path.clone(),
+            request: HistoryRequest {
+                method: resolved.method.clone(),
+                url: variables.mask_str(&resolved.url),
+                headers: masked_headers(&resolved.headers, variables),
+                body: resolved.body.as_ref().map(|body| variables.mask_json(body)),
+            },
+            ..HistoryEntry::default()
+        }
+    }
+
+    /// Fill in the status, headers and body of the response
+    pub fn set_response(&mut self, response: &CapturedResponse, variables: &VariableStore) {
+        self.status = Some(response.status.as_u16());
+        self.response_headers = masked_headers(&response.headers, variables);
+        self.response_body = variables.mask_str(&response.text);
+    }
+
+    /// The time of the entry in the local time zone
+    pub fn local_time(&self) -> String {
+        DateTime::parse_from_rfc3339(&self.timestamp)
+            .map(|time| {
+                time.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|_| self.timestamp.clone())
+    }
+
+    /// Whether the tag, title, environment, URL, bodies or error contain `text`, ignoring case
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        let request_body = self
+            .request
+            .body
+            .as_ref()
+            .map(Value::to_string)
+            .unwrap_or_default();
+        [
+            self.tag.as_str(),
+            &self.title,
+            &self.environment,
+            &self.request.url,
+            &request_body,
+            &self.response_body,
+            self.error.as_deref().unwrap_or_default(),
+        ]
+        .iter()
+        .any(|field| field.to_lowercase().contains(&text))
+    }
+
+    /// Print the entry on one line: id, time, status, method, tag, URL and duration
+    pub fn display_line(&self) {
+        let status = match self.status {
+            Some(status) if (200..300).contains(&status) => status.to_string().green(),
+            Some(status) => status.to_string().red(),
+            None => "---".red(),
+        };
+        println!(
+            "{} {} {} {} {} {} {}",
+            format!("#{}", self.id).purple(),
+            self.local_time(),
+            status,
+            self.request.method.blue(),
+            self.tag.green(),
+            self.request.url.yellow(),
+            format!("{} ms", self.duration_ms).purple()
+        );
+    }
+
+    /// Print the entry with its request and response
+    pub fn display(&self) {
+        let display_body = |body: &Value| {
+            println!();
+            display_colored_json(body, 0);
+            println!();
+        };
+
+        println!(
+            "{} {} {}",
+            "ENTRY    :".blue().bold(),
+            format!("#{}", self.id).purple(),
+            self.local_time()
+        );
+        println!("{} {}", "TITLE    :".blue().bold(), self.title.green());
+        println!("{} {}", "TAG      :".blue().bold(), self.tag.green());
+        println!(
+            "{} {}",
+            "ENV      :".blue().bold(),
+            self.environment.yellow()
+        );
+        println!(
+            "{} {} {}",
+            "URL      :".blue().bold(),
+            self.request.method.blue(),
+            self.request.url.yellow()
+        );
+        println!("{}", "Request  :".blue().bold());
+        for (name, value) in &self.request.headers {
+            println!("  {}: {}", name.blue(), value);
+        }
+        if let Some(body) = &self.request.body {
+            display_body(body);
+        }
+
+        match (self.status, &self.error) {
+            (Some(status), _) => println!(
+                "{} {} {}",
+                "Status   :".blue().bold(),
+                status.to_string().green(),
+                format!("({} ms)", self.duration_ms).purple()
+            ),
+            (None, error) => {
+                println!("{} {}", "Status   :".blue().bold(), "no response".red());
+                if let Some(error) = error {
+                    println!("{}", error.red());
+                }
+                return;
+            }
+        }
+        println!("{}", "Response :".blue().bold());
+        for (name, value) in &self.response_headers {
+            println!("  {}: {}", name.blue(), value);
+        }
+        match serde_json::from_str::<Value>(&self.response_body) {
+            Ok(body) => display_body(&body),
+            Err(_) => println!("\n{}", self.response_body),
+        }
+    }
+}
+
+/// The history file of a collection, every run appends its executions to it
+pub struct History {
+    path: PathBuf,
+    limit: usize,
+}
+
+impl History {
+    pub fn new(path: &Path, config: &HistoryConfig) -> History {
+        History {
+            path: path.to_path_buf(),
+            limit: config.limit,
+        }
+    }
+
+    /// A history that records nothing, for collections without `variable_dir`
+    pub fn disabled() -> History {
+        History {
+            path: PathBuf::new(),
+            limit: 0,
+        }
+    }
+
+    /// Every entry, the oldest first
+    pub fn entries(&self) -> io::Result<Vec<HistoryEntry>> {
+        if self.path.as_os_str().is_empty() {
+            return Ok(Vec::new());
+        }
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                serde_json::from_str(line)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .collect()
+    }
+
+    /// The entries of `tag` that contain `text`, the oldest first
+    pub fn search(&self, tag: Option<&str>, text: Option<&str>) -> io::Result<Vec<HistoryEntry>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter(|entry| tag.is_none_or(|tag| entry.tag == tag))
+            .filter(|entry| text.is_none_or(|text| entry.matches(text)))
+            .collect())
+    }
+
+    /// The entry with this id
+    pub fn find(&self, id: u64) -> io::Result<Option<HistoryEntry>> {
+        Ok(self.entries()?.into_iter().find(|entry| entry.id == id))
+    }
+
+    /// Save an entry with the next id, dropping the oldest ones past the limit
+    pub fn record(&self, mut entry: HistoryEntry) -> io::Result<u64> {
+        if self.limit == 0 {
+            return Ok(0);
+        }
+
+        // Parallel runs share the file, the lock covers the read as well as the write
+        let lock = lock_for(&self.path)?;
+        let mut entries = self.entries()?;
+        entry.id = entries.last().map_or(1, |last| last.id + 1);
+        let id = entry.id;
+        entries.push(entry);
+        let skip = entries.len().saturating_sub(self.limit);
+
+        let mut contents = String::new();
+        for entry in &entries[skip..] {
+            let line = serde_json::to_string(entry)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            contents.push_str(&line);
+            contents.push('\n');
+        }
+        write_atomic_locked(&self.path, contents.as_bytes(), &lock)?;
+        Ok(id)
+    }
+
+    /// Send a resolved request, capture its values and save it to the history.
+    /// The error comes back masked.
+    pub async fn execute(
+        &self,
+        app_main_request: &AppMainRequest,
+        request: &RequestData,
+        resolved: &ResolvedRequest,
+        variables: &VariableStore,
+        cookie_jar: &Option<Arc<CookieStoreMutex>>,
+        overrides: &RequestOverrides,
+    ) -> Result<CapturedResponse, String> {
+        let entry = HistoryEntry::new(app_main_request, request, resolved, overrides, variables);
+        let send = app_main_request.execute(request, variables, cookie_jar, overrides);
+        self.record_outcome(app_main_request, Some(request), entry, variables, send)
+            .await
+    }
+
+    /// Send the request of an entry again as it was saved: method, URL, headers and body.
+    /// Auth and captures are applied again from its tag, or the collection auth when the tag is gone.
+    /// A request with masked secret values cannot be sent again.
+    pub async fn resend(
+        &self,
+        app_main_request: &AppMainRequest,
+        entry: &HistoryEntry,
+        variables: &VariableStore,
+        cookie_jar: &Option<Arc<CookieStoreMutex>>,
+    ) -> Result<CapturedResponse, String> {
+        let stored = serde_json::to_string(&entry.request).map_err(|e| e.to_string())?;
+        if stored.contains(MASK) {
+            return Err(format!(
+                "History entry {} has masked secret values, send its tag again instead",
+                entry.id
+            ));
+        }
+
+        let tagged = app_main_request.request(&entry.tag);
+        let request = match tagged {
+            Some(request) => request.clone(),
+            None => {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Item not found: {}, sending with the collection auth and client settings",
+                        entry.tag
+                    )
+                    .yellow()
+                );
+                RequestData::default()
+            }
+        };
+        let resent = HistoryEntry {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            environment: entry.environment.clone(),
+            tag: entry.tag.clone(),
+            title: entry.title.clone(),
+            query: entry.query.clone(),
+            path: entry.path.clone(),
+            request: entry.request.clone(),
+            ..HistoryEntry::default()
+        };
+        let send = send_stored_request(
+            app_main_request,
+            &request,
+            &entry.request,
+            variables,
+            cookie_jar,
+        );
+        self.record_outcome(app_main_request, tagged, resent, variables, send)
+            .await
+    }
+
+    /// Wait for `send`, capture the values of `request` and save the entry with its response
+    /// or error. Capturing comes first, so a secret token in the response is saved masked.
+    async fn record_outcome(
+        &self,
+        app_main_request: &AppMainRequest,
+        request: Option<&RequestData>,
+        mut entry: HistoryEntry,
+        variables: &VariableStore,
+        send: impl Future<Output = Result<CapturedResponse, Box<dyn std::error::Error>>>,
+    ) -> Result<CapturedResponse, String> {
+        let started = Instant::now();
+        let result = send.await;
+        entry.duration_ms = started.elapsed().as_millis() as u64;
+
+        let result = match result {
+            Ok(captured) => {
+                // Captured values last for the run, `req_variable_is_save` also writes them to disk
+                if let Some(request) = request {
+                    if let Err(err) = app_main_request.capture(request, &captured, variables) {
+                        display_error(err);
+                    }
+                }
+                entry.set_response(&captured, variables);
+                Ok(captured)
+            }
+            Err(err) => {
+                let message = variables.mask_str(&err.to_string());
+                entry.error = Some(message.clone());
+                Err(message)
+            }
+        };
+        if let Err(err) = self.record(entry) {
+            eprintln!("{}", format!("History not saved: {}", err).red());
+        }
+        result
+    }
+
+    /// Delete the history file, returning how many entries it had
+    pub fn clear(&self) -> io::Result<usize> {
+        let count = self.entries()?.len();
+        if self.path.exists() {
+            remove_file(&self.path)?;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_writers_get_distinct_ids_and_lose_nothing() {
+        let dir = std::env::temp_dir().join(format!("rest_api_history_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("_variables.history.jsonl");
+
+        // One History per thread, like separate runs sharing the file
+        let writers: Vec<_> = (0..8)
+            .map(|writer| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let history = History::new(&path, &HistoryConfig::default());
+                    for _ in 0..5 {
+                        let entry = HistoryEntry {
+                            tag: format!("writer_{}", writer),
+                            ..HistoryEntry::default()
+                        };
+                        history.record(entry).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let entries = History::new(&path, &HistoryConfig::default())
+            .entries()
+            .unwrap();
+        let ids: Vec<u64> = entries.iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, (1..=40).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,56 @@
+//! Load an API collection, resolve its requests and send them.
+//!
+//! The `rest_api` binary is a thin wrapper around this crate, so integration tests
+//! can drive the same collection files in code:
+//!
+//! ```no_run
+//! use rest_api::{AppMainRequest, RequestOverrides};
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let collection = AppMainRequest::load("~/api/users.json")?;
+//! let variables = collection.variables();
+//! let request = collection.request("user_get_all").ok_or("unknown tag")?;
+//!
+//! let overrides = RequestOverrides::default();
+//! let resolved = collection.resolve(request, &variables, &overrides)?;
+//! println!("{} {}", resolved.method, resolved.url);
+//!
+//! let response = collection
+//!     .execute(request, &variables, &None, &overrides)
+//!     .await?;
+//! collection.capture(request, &response, &variables)?;
+//! assert!(response.status.is_success());
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod auth;
+pub mod bench;
+pub mod capture;
+pub mod client;
+pub mod collection;
+pub mod compare;
+pub mod cookies;
+pub mod data;
+pub mod display;
+pub mod file_ops;
+pub mod filter;
+pub mod history;
+pub mod mock;
+pub mod oauth2;
+pub mod path_params;
+pub mod query;
+pub mod record;
+pub mod request;
+pub mod retry;
+pub mod schema;
+pub mod secrets;
+pub mod signing;
+pub mod snapshot;
+pub mod variables;
+pub mod workflow;
+
+pub use capture::CapturedResponse;
+pub use collection::{AppMainRequest, RequestData, RequestDataBody};
+pub use request::{RequestOverrides, ResolvedRequest};
+pub use variables::VariableStore;
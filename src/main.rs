@@ -1,513 +1,1129 @@
-use std::{
-    collections::HashMap,
-    fs::File,
-    io::{self, BufReader},
-    path::Path,
-};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command};
 use colored::*;
-use reqwest::{
-    blocking::multipart,
-    header::{HeaderMap, HeaderName, HeaderValue},
-};
-use serde::Deserialize;
+use reqwest::StatusCode;
+use reqwest_cookie_store::CookieStoreMutex;
 use serde_json::Value;
 
-mod file_ops;
-use file_ops::{expand_tilde, read_from_file, write_to_file};
+use rest_api::bench::{bench_request, BenchConfig};
+use rest_api::compare::{display_comparison, Side};
+use rest_api::cookies::{
+    clear_cookies, cookie_jar_path, list_cookies, load_cookie_jar, save_cookie_jar,
+};
+use rest_api::data::{all_succeeded, display_iteration_summary, load_data_rows, IterationResult};
+use rest_api::display::{display_colored_json, display_error, display_output};
+use rest_api::file_ops::expand_tilde;
+use rest_api::filter::{Filter, OutputFormat};
+use rest_api::history::History;
+use rest_api::mock::MockServer;
+use rest_api::record::Recorder;
+use rest_api::schema::display_schema_violations;
+use rest_api::secrets::MASK;
+use rest_api::snapshot::SnapshotRun;
+use rest_api::workflow::display_workflow_summary;
+use rest_api::{AppMainRequest, RequestData, RequestOverrides, VariableStore};
 
-#[derive(Debug, Default, Deserialize)]
-struct AppMainRequest {
-    base_url: String,
-    headers: Option<HashMap<String, String>>,
-    variable_dir: Option<String>,
-    variable_access_token_file: Option<String>,
-    requests: Vec<RequestData>,
+/// Where a run records its requests, what it checks the responses against and how many failed
+struct Run {
+    history: History,
+    snapshots: Option<SnapshotRun>,
+    schema_failures: AtomicUsize,
+    filter: Option<Filter>,
+    format: OutputFormat,
 }
 
-impl AppMainRequest {
-    pub fn create_header_map(&self) -> HeaderMap {
-        let mut header_map = HeaderMap::new();
+impl Run {
+    /// The history, snapshots and schema checks of a run of this collection
+    fn new(app_main_request: &AppMainRequest, snapshots: Option<SnapshotRun>) -> Run {
+        Run {
+            history: app_main_request.history(),
+            snapshots,
+            schema_failures: AtomicUsize::new(0),
+            filter: None,
+            format: OutputFormat::Pretty,
+        }
+    }
 
-        if let Some(headers) = &self.headers {
-            for (key, value) in headers {
-                if let Ok(header_name) = HeaderName::from_bytes(key.as_bytes()) {
-                    if let Ok(header_value) = HeaderValue::from_str(value) {
-                        header_map.insert(header_name, header_value);
-                    }
-                }
-            }
+    /// Print the summaries, true when a snapshot or a schema check failed
+    fn failed(&self) -> bool {
+        let schema_failures = self.schema_failures.load(Ordering::Relaxed);
+        if schema_failures > 0 {
+            eprintln!(
+                "{} {}",
+                "SCHEMAS  :".blue().bold(),
+                format!("{} failed", schema_failures).red()
+            );
         }
 
-        header_map
+        let snapshots_failed = self.snapshots.as_ref().is_some_and(|snapshots| {
+            snapshots.display_summary();
+            snapshots.failed()
+        });
+        snapshots_failed || schema_failures > 0
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
-struct RequestData {
-    req_tag: String,
-    req_title: String,
-    req_type: String,
-    req_end_point: String,
-    req_params: Option<String>,
-    req_variable_type: Option<String>,
-    req_variable_is_save: Option<bool>,
-    req_variable_response_value: Option<Value>,
-    req_body: Option<RequestDataBody>,
+/// Parse a `KEY=VALUE` command line argument
+fn parse_key_value(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected KEY=VALUE, got '{}'", arg)),
+    }
 }
 
-#[derive(Clone, Debug, Deserialize)]
-struct RequestDataBody {
-    body_type: String,
-    body_file: String,
-    file_paths: Option<Vec<String>>,
+/// Parse a `--rate`, which must be a positive number of requests per second
+fn parse_rate(arg: &str) -> Result<f64, String> {
+    match arg.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("expected a positive number, got '{}'", arg)),
+    }
 }
 
-fn display_colored_json(value: &Value, indent_level: usize) {
-    match value {
-        Value::Object(map) => {
-            print!("{}", "{".blue());
-            let len = map.len();
-            let iter = map.iter().enumerate();
-            for (i, (key, val)) in iter {
-                print!("\n{}", " ".repeat(indent_level + 4));
-                print!("\"{}\": ", key.blue()); // Key in blue with quotes
-                display_colored_json(val, indent_level + 4);
-                if i != len - 1 {
-                    print!(",");
-                }
-            }
-            print!("\n{}", " ".repeat(indent_level));
-            print!("{}", "}".blue());
+/// Load the API FILE, printing why it could not be read
+fn load_collection(file_data: &str) -> Option<AppMainRequest> {
+    AppMainRequest::load(file_data).map_err(display_error).ok()
+}
+
+/// The optional cookie jar, reloaded from disk on every run
+fn open_cookie_jar(app_main_request: &AppMainRequest) -> (PathBuf, Option<Arc<CookieStoreMutex>>) {
+    let variable_dir = app_main_request.variable_dir.clone().unwrap_or_default();
+    let jar_path = cookie_jar_path(&variable_dir);
+    if !app_main_request.cookie_jar.unwrap_or_default() {
+        return (jar_path, None);
+    }
+
+    match load_cookie_jar(&jar_path) {
+        Ok(jar) => (jar_path, Some(jar)),
+        Err(err) => {
+            eprintln!("{}", format!("Cookie jar not loaded: {}", err).red());
+            (jar_path, None)
         }
-        Value::Array(arr) => {
-            print!("{}", "[".blue());
-            let len = arr.len();
-            let iter = arr.iter().enumerate();
-            for (i, val) in iter {
-                print!("\n{}", " ".repeat(indent_level + 4));
-                display_colored_json(val, indent_level + 4);
-                if i != len - 1 {
-                    print!(",");
-                }
-            }
-            print!("\n{}", " ".repeat(indent_level));
-            print!("{}", "]".blue());
+    }
+}
+
+fn close_cookie_jar(cookie_jar: &Option<Arc<CookieStoreMutex>>, jar_path: &Path) {
+    if let Some(jar) = cookie_jar {
+        if let Err(err) = save_cookie_jar(jar, jar_path) {
+            eprintln!("{}", format!("Cookie jar not saved: {}", err).red());
         }
-        Value::String(s) => print!("\"{}\"", s.yellow()),
-        Value::Number(num) => print!("{}", num.to_string().green()),
-        Value::Bool(b) => print!("{}", b.to_string().purple()),
-        Value::Null => print!("{}", "null".red()),
     }
 }
 
-fn main() {
+fn file_arg() -> Arg {
+    Arg::new("file")
+        .short('f')
+        .long("file")
+        .value_name("FILE")
+        .help("The API FILE")
+        .required(true)
+}
+
+fn host_arg() -> Arg {
+    Arg::new("host")
+        .long("host")
+        .value_name("HOST")
+        .help("Address to listen on")
+        .default_value("127.0.0.1")
+}
+
+fn port_arg() -> Arg {
+    Arg::new("port")
+        .short('p')
+        .long("port")
+        .value_name("PORT")
+        .help("Port to listen on")
+        .value_parser(clap::value_parser!(u16))
+        .default_value("8080")
+}
+
+fn query_arg() -> Arg {
+    Arg::new("query")
+        .long("query")
+        .value_name("KEY=VALUE")
+        .help("Set a query parameter, replacing the req_query values for KEY")
+        .value_parser(parse_key_value)
+        .action(ArgAction::Append)
+}
+
+fn path_arg() -> Arg {
+    Arg::new("path")
+        .long("path")
+        .value_name("KEY=VALUE")
+        .help("Fill the :KEY or {KEY} segment of req_end_point")
+        .value_parser(parse_key_value)
+        .action(ArgAction::Append)
+}
+
+fn var_arg() -> Arg {
+    Arg::new("var")
+        .long("var")
+        .value_name("KEY=VALUE")
+        .help("Use VALUE for {{KEY}} in this run, without saving it")
+        .value_parser(parse_key_value)
+        .action(ArgAction::Append)
+}
+
+fn id_arg() -> Arg {
+    Arg::new("id")
+        .value_name("ID")
+        .help("The history entry, as listed by `history list`")
+        .value_parser(clap::value_parser!(u64))
+        .required(true)
+}
+
+fn last_arg() -> Arg {
+    Arg::new("last")
+        .short('n')
+        .long("last")
+        .value_name("N")
+        .help("How many of the latest entries to list")
+        .value_parser(clap::value_parser!(usize))
+        .default_value("20")
+}
+
+fn reveal_arg() -> Arg {
+    Arg::new("reveal")
+        .long("reveal")
+        .help("Show secret values instead of masking them")
+        .action(ArgAction::SetTrue)
+}
+
+#[tokio::main]
+async fn main() {
     let matches = Command::new("API CLI")
         .version("1.0")
         .author("Irmansyah <irmansyahproject@gmail.com>")
         .about("CLI app for making API requests")
-        .arg(
-            Arg::new("file")
-                .short('f')
-                .long("file")
-                .value_name("FILE")
-                .help("The API FILE")
-                .required(true),
-        )
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
+        .arg(file_arg())
         .arg(
             Arg::new("tag")
                 .short('t')
                 .long("tag")
                 .value_name("TAG")
-                .help("The API FILE TAG")
+                .help("The API FILE TAG, repeat it to run several requests in order")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("workflow")
+                .short('w')
+                .long("workflow")
+                .value_name("NAME")
+                .help("Run a workflow from the API FILE, independent steps concurrently"),
+        )
+        .arg(
+            Arg::new("parallel")
+                .long("parallel")
+                .value_name("N")
+                .help("Requests in flight at once in a workflow (overrides its \"parallel\")")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .group(
+            ArgGroup::new("target")
+                .args(["tag", "workflow"])
                 .required(true),
         )
+        .arg(query_arg())
+        .arg(path_arg())
+        .arg(
+            Arg::new("data")
+                .long("data")
+                .value_name("FILE")
+                .help("Run the tags once per row of a CSV or JSON array file"),
+        )
+        .arg(var_arg())
+        .arg(
+            Arg::new("filter")
+                .long("filter")
+                .value_name("EXPR")
+                .help("Show only what a jq-like EXPR picks from each response body, e.g. '.data[] | {id, name}'")
+                .value_parser(Filter::parse),
+        )
+        .arg(
+            Arg::new("raw")
+                .short('r')
+                .long("raw")
+                .help("Print only the body, one value per line and strings without quotes")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("compact")
+                .short('c')
+                .long("compact")
+                .help("Print only the body, one JSON value per line")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("raw"),
+        )
+        .arg(
+            Arg::new("snapshot")
+                .long("snapshot")
+                .help("Compare each response body with its saved snapshot, fail on differences")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("update-snapshots")
+                .long("update-snapshots")
+                .help("Save the response bodies as the new snapshots")
+                .action(ArgAction::SetTrue),
+        )
+        .subcommand(
+            Command::new("cookies")
+                .about("Manage the cookie jar saved next to variable_dir")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("list")
+                        .about("List the saved cookies")
+                        .arg(file_arg()),
+                )
+                .subcommand(
+                    Command::new("clear")
+                        .about("Delete the saved cookies")
+                        .arg(file_arg()),
+                ),
+        )
+        .subcommand(
+            Command::new("bench")
+                .about("Load-test a request tag and report latency and throughput")
+                .arg(file_arg())
+                .arg(
+                    Arg::new("tag")
+                        .short('t')
+                        .long("tag")
+                        .value_name("TAG")
+                        .help("The API FILE TAG")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("requests")
+                        .short('n')
+                        .long("requests")
+                        .value_name("N")
+                        .help("Total number of requests")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("100"),
+                )
+                .arg(
+                    Arg::new("concurrency")
+                        .short('c')
+                        .long("concurrency")
+                        .value_name("C")
+                        .help("Requests in flight at once")
+                        .value_parser(clap::value_parser!(usize))
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::new("rate")
+                        .long("rate")
+                        .value_name("PER_SECOND")
+                        .help("Fixed-rate mode: start PER_SECOND requests per second")
+                        .value_parser(parse_rate),
+                )
+                .arg(var_arg()),
+        )
+        .subcommand(
+            Command::new("mock")
+                .about("Serve the requests of the API FILE with their mock responses")
+                .arg(file_arg())
+                .arg(host_arg())
+                .arg(port_arg()),
+        )
+        .subcommand(
+            Command::new("record")
+                .about("Proxy a backend and record the requests into a collection")
+                .arg(
+                    Arg::new("target")
+                        .long("target")
+                        .value_name("URL")
+                        .help("The backend base_url to forward to")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("out")
+                        .short('o')
+                        .long("out")
+                        .value_name("DIR")
+                        .help("Directory for collection.json, the bodies and the fixtures")
+                        .required(true),
+                )
+                .arg(host_arg())
+                .arg(port_arg()),
+        )
+        .subcommand(
+            Command::new("replay")
+                .about("Serve the responses recorded by `record`, without the backend")
+                .arg(file_arg())
+                .arg(host_arg())
+                .arg(port_arg()),
+        )
+        .subcommand(
+            Command::new("compare")
+                .about("Diff the status, headers and body of two responses")
+                .arg(file_arg())
+                .arg(
+                    Arg::new("tag")
+                        .short('t')
+                        .long("tag")
+                        .value_name("TAG")
+                        .help("The API FILE TAG to send to both sides")
+                        .conflicts_with("history"),
+                )
+                .arg(
+                    Arg::new("base-url")
+                        .long("base-url")
+                        .value_names(["LEFT", "RIGHT"])
+                        .help("The two base_url the tag is sent to, with the variables and auth of FILE")
+                        .num_args(2)
+                        .requires("tag"),
+                )
+                .arg(
+                    Arg::new("right-file")
+                        .long("right-file")
+                        .value_name("FILE")
+                        .help("The API FILE of the right side, e.g. production next to a staging FILE")
+                        .requires("tag"),
+                )
+                .arg(
+                    Arg::new("history")
+                        .long("history")
+                        .value_names(["LEFT", "RIGHT"])
+                        .help("Compare two history entries instead")
+                        .value_parser(clap::value_parser!(u64))
+                        .num_args(2),
+                )
+                .group(
+                    ArgGroup::new("sides")
+                        .args(["base-url", "right-file", "history"])
+                        .required(true),
+                )
+                .arg(query_arg())
+                .arg(path_arg())
+                .arg(var_arg()),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Browse and re-send the requests saved next to variable_dir")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("list")
+                        .about("List the latest requests")
+                        .arg(file_arg())
+                        .arg(last_arg())
+                        .arg(
+                            Arg::new("tag")
+                                .short('t')
+                                .long("tag")
+                                .value_name("TAG")
+                                .help("Only the requests of this tag"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Print a request and its response")
+                        .arg(file_arg())
+                        .arg(id_arg()),
+                )
+                .subcommand(
+                    Command::new("search")
+                        .about("List the requests whose tag, URL, bodies or base_url contain TEXT")
+                        .arg(file_arg())
+                        .arg(last_arg())
+                        .arg(Arg::new("text").value_name("TEXT").required(true)),
+                )
+                .subcommand(
+                    Command::new("resend")
+                        .about("Send a saved request again: its method, URL, headers and body")
+                        .arg(file_arg())
+                        .arg(id_arg())
+                        .arg(var_arg()),
+                )
+                .subcommand(
+                    Command::new("clear")
+                        .about("Delete the saved history")
+                        .arg(file_arg()),
+                ),
+        )
+        .subcommand(
+            Command::new("vars")
+                .about("Manage the variables saved in variable_dir")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("list")
+                        .about("List the saved variables")
+                        .arg(file_arg())
+                        .arg(reveal_arg()),
+                )
+                .subcommand(
+                    Command::new("get")
+                        .about("Print a variable")
+                        .arg(file_arg())
+                        .arg(reveal_arg())
+                        .arg(Arg::new("name").value_name("NAME").required(true)),
+                )
+                .subcommand(
+                    Command::new("set")
+                        .about("Save a variable")
+                        .arg(file_arg())
+                        .arg(Arg::new("name").value_name("NAME").required(true))
+                        .arg(Arg::new("value").value_name("VALUE").required(true)),
+                )
+                .subcommand(
+                    Command::new("unset")
+                        .about("Delete a variable")
+                        .arg(file_arg())
+                        .arg(Arg::new("name").value_name("NAME").required(true)),
+                )
+                .subcommand(
+                    Command::new("clear")
+                        .about("Delete every saved variable")
+                        .arg(file_arg()),
+                ),
+        )
         .get_matches();
 
+    if let Some(("bench", sub_matches)) = matches.subcommand() {
+        let file_data = sub_matches
+            .get_one::<String>("file")
+            .expect("file is required");
+        let tag = sub_matches
+            .get_one::<String>("tag")
+            .expect("tag is required");
+        let var_overrides: Vec<(String, String)> = sub_matches
+            .get_many::<(String, String)>("var")
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default();
+        let config = BenchConfig {
+            requests: *sub_matches.get_one::<usize>("requests").unwrap_or(&100),
+            concurrency: *sub_matches.get_one::<usize>("concurrency").unwrap_or(&10),
+            rate: sub_matches.get_one::<f64>("rate").copied(),
+        };
+
+        let Some(app_main_request) = load_collection(file_data) else {
+            return;
+        };
+        let Some(request) = app_main_request.request(tag) else {
+            eprintln!("Item not found: {}", tag);
+            std::process::exit(1);
+        };
+        let variables = app_main_request.variables().with_overrides(&var_overrides);
+
+        match bench_request(&app_main_request, request, &variables, &config).await {
+            Ok(report) => report.display(),
+            Err(err) => display_error(variables.mask_str(&err.to_string())),
+        }
+        return;
+    }
+
+    if let Some(("compare", sub_matches)) = matches.subcommand() {
+        if !run_compare(sub_matches).await {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(("history", sub_matches)) = matches.subcommand() {
+        if let Some((action, sub_matches)) = sub_matches.subcommand() {
+            run_history(action, sub_matches).await;
+        }
+        return;
+    }
+
+    if let Some((command @ ("mock" | "replay" | "record"), sub_matches)) = matches.subcommand() {
+        let host = sub_matches
+            .get_one::<String>("host")
+            .map_or("127.0.0.1", String::as_str);
+        let port = *sub_matches.get_one::<u16>("port").unwrap_or(&8080);
+
+        if command == "record" {
+            let target = sub_matches
+                .get_one::<String>("target")
+                .expect("target is required");
+            let out_dir = sub_matches
+                .get_one::<String>("out")
+                .expect("out is required");
+            let result = match Recorder::new(target, out_dir) {
+                Ok(recorder) => recorder.serve(host, port).await.map_err(Into::into),
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
+                display_error(err);
+            }
+            return;
+        }
+
+        let file_data = sub_matches
+            .get_one::<String>("file")
+            .expect("file is required");
+        let Some(app_main_request) = load_collection(file_data) else {
+            return;
+        };
+        let server = match command {
+            "replay" => MockServer::replay(&app_main_request),
+            _ => MockServer::new(&app_main_request),
+        };
+        if let Err(err) = server.serve(host, port).await {
+            display_error(err);
+        }
+        return;
+    }
+
+    if let Some((command, Some((action, sub_matches)))) = matches
+        .subcommand()
+        .map(|(name, sub_matches)| (name, sub_matches.subcommand()))
+    {
+        let file_data = sub_matches
+            .get_one::<String>("file")
+            .expect("file is required");
+        let Some(app_main_request) = load_collection(file_data) else {
+            return;
+        };
+
+        let result = match command {
+            "cookies" => {
+                let variable_dir = app_main_request.variable_dir.unwrap_or_default();
+                let jar_path = cookie_jar_path(&variable_dir);
+                match action {
+                    "clear" => clear_cookies(&jar_path),
+                    _ => list_cookies(&jar_path),
+                }
+            }
+            _ => {
+                let variables = app_main_request.variables();
+                let reveal = sub_matches
+                    .try_get_one::<bool>("reveal")
+                    .ok()
+                    .flatten()
+                    .copied()
+                    .unwrap_or_default();
+                let name = sub_matches
+                    .try_get_one::<String>("name")
+                    .ok()
+                    .flatten()
+                    .map(String::as_str);
+                match (action, name) {
+                    ("get", Some(name)) => variables.get(name).map(|value| {
+                        if variables.is_secret(name) && !reveal {
+                            println!("{}", MASK);
+                        } else {
+                            println!("{}", value);
+                        }
+                    }),
+                    ("set", Some(name)) => {
+                        let value = sub_matches
+                            .get_one::<String>("value")
+                            .expect("value is required");
+                        variables.set(name, value)
+                    }
+                    ("unset", Some(name)) => variables.unset(name),
+                    ("clear", _) => variables.clear().map(|count| {
+                        println!("Cleared: {} variables", count.to_string().yellow().bold())
+                    }),
+                    _ => variables.display(reveal),
+                }
+            }
+        };
+        if let Err(err) = result {
+            display_error(err);
+        }
+        return;
+    }
+
     let file = matches.get_one::<String>("file");
-    let tag = matches.get_one::<String>("tag");
+    let tags: Vec<String> = matches
+        .get_many::<String>("tag")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default();
+    let overrides = RequestOverrides {
+        query: matches
+            .get_many::<(String, String)>("query")
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default(),
+        path: matches
+            .get_many::<(String, String)>("path")
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default(),
+    };
+    let var_overrides: Vec<(String, String)> = matches
+        .get_many::<(String, String)>("var")
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default();
+    let data_file = matches.get_one::<String>("data");
+    let filter = matches.get_one::<Filter>("filter");
+    let format = if matches.get_flag("raw") {
+        OutputFormat::Raw
+    } else if matches.get_flag("compact") {
+        OutputFormat::Compact
+    } else {
+        OutputFormat::Pretty
+    };
+    let workflow_name = matches.get_one::<String>("workflow");
+    let parallel = matches.get_one::<usize>("parallel").copied();
+    let update_snapshots = matches.get_flag("update-snapshots");
+    let snapshots = (matches.get_flag("snapshot") || update_snapshots).then(|| {
+        // Next to the API FILE unless "snapshot": { "dir": ... } says otherwise
+        let file = expand_tilde(file.map_or("", String::as_str));
+        let dir = Path::new(&file).parent().unwrap_or(Path::new("."));
+        SnapshotRun::new(dir.join("snapshots"), update_snapshots)
+    });
 
     if let Some(file_data) = file {
-        let app_main_request = app_main_request(file_data).unwrap_or_default();
+        let Some(app_main_request) = load_collection(file_data) else {
+            return;
+        };
+
+        let variables = app_main_request.variables().with_overrides(&var_overrides);
+        let run = Run {
+            filter: filter.cloned(),
+            format,
+            ..Run::new(&app_main_request, snapshots)
+        };
+        let (jar_path, cookie_jar) = open_cookie_jar(&app_main_request);
+
+        let workflow = match workflow_name {
+            Some(name) => {
+                match app_main_request
+                    .workflow(name)
+                    .map(|workflow| (workflow, workflow.validate()))
+                {
+                    Some((workflow, Ok(()))) => Some(workflow),
+                    Some((_, Err(err))) => {
+                        display_error(err);
+                        std::process::exit(1);
+                    }
+                    None => {
+                        eprintln!("Workflow not found: {}", name);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            None => None,
+        };
+
+        // `row` names the snapshots of a data iteration apart
+        let run_target = |variables: VariableStore, row: Option<usize>| {
+            let (app_main_request, tags, cookie_jar) = (&app_main_request, &tags, &cookie_jar);
+            let (overrides, run) = (&overrides, &run);
+            let check_name = move |name: &str| match row {
+                Some(row) => format!("{}.{}", name, row),
+                None => name.to_string(),
+            };
+            async move {
+                let mut statuses = Vec::new();
+
+                if let Some(workflow) = workflow {
+                    // Concurrent steps print one line each instead of their whole response
+                    let parallel = parallel.unwrap_or(workflow.parallel);
+                    eprintln!(
+                        "{} {} {}",
+                        "WORKFLOW :".blue().bold(),
+                        workflow.name.green(),
+                        format!("(parallel {})", parallel).purple()
+                    );
+                    let results = workflow
+                        .run(parallel, |step| {
+                            let (variables, tag) = (&variables, step.tag.clone());
+                            let check = (run, check_name(step.id()));
+                            async move {
+                                let Some(request) = app_main_request.request(&tag) else {
+                                    eprintln!("Item not found: {}", tag);
+                                    return None;
+                                };
+                                run_request(
+                                    app_main_request,
+                                    request,
+                                    variables,
+                                    cookie_jar,
+                                    overrides,
+                                    check,
+                                    parallel == 1,
+                                )
+                                .await
+                            }
+                        })
+                        .await;
+                    display_workflow_summary(&results);
+                    return results
+                        .into_iter()
+                        .map(|result| (result.id, result.status))
+                        .collect();
+                }
+
+                // Every tag runs in order and shares the run-scoped variables
+                for tag in tags {
+                    let Some(request) = app_main_request.request(tag) else {
+                        eprintln!("Item not found: {}", tag);
+                        statuses.push((tag.clone(), None));
+                        break;
+                    };
+                    let status = run_request(
+                        app_main_request,
+                        request,
+                        &variables,
+                        cookie_jar,
+                        overrides,
+                        (run, check_name(tag)),
+                        true,
+                    )
+                    .await;
+                    statuses.push((tag.clone(), status));
+                    if status.is_none() {
+                        break;
+                    }
+                }
+                statuses
+            }
+        };
+
+        // A failed tag, step or iteration makes the run exit with 1
+        let mut failed = false;
+        match data_file {
+            // One iteration per data row, each with its own captured variables
+            Some(data_file) => match load_data_rows(data_file) {
+                Ok(rows) => {
+                    let mut results = Vec::new();
+                    for (index, row) in rows.iter().enumerate() {
+                        eprintln!();
+                        eprintln!(
+                            "{} {}/{}",
+                            "ITERATION:".blue().bold(),
+                            index + 1,
+                            rows.len()
+                        );
+                        results.push(IterationResult {
+                            row: index + 1,
+                            statuses: run_target(variables.for_row(row), Some(index + 1)).await,
+                        });
+                    }
+                    display_iteration_summary(&results);
+                    failed = results.iter().any(|result| !result.passed());
+                }
+                Err(err) => display_error(err),
+            },
+            None => failed = !all_succeeded(&run_target(variables, None).await),
+        }
+
+        close_cookie_jar(&cookie_jar, &jar_path);
+
+        if run.failed() || failed {
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The `compare` subcommand, false when the responses differ
+async fn run_compare(sub_matches: &ArgMatches) -> bool {
+    let file_data = sub_matches
+        .get_one::<String>("file")
+        .expect("file is required");
+
+    let Some(app_main_request) = load_collection(file_data) else {
+        return false;
+    };
+
+    let sides = if let Some(ids) = sub_matches.get_many::<u64>("history") {
+        let history = app_main_request.history();
+        match ids
+            .map(|id| Side::from_history(&history, *id))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(sides) => sides,
+            Err(err) => {
+                display_error(err);
+                return false;
+            }
+        }
+    } else {
+        let tag = sub_matches
+            .get_one::<String>("tag")
+            .expect("tag is required");
+        let var_overrides: Vec<(String, String)> = sub_matches
+            .get_many::<(String, String)>("var")
+            .map(|values| values.cloned().collect())
+            .unwrap_or_default();
+        let overrides = RequestOverrides {
+            query: sub_matches
+                .get_many::<(String, String)>("query")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default(),
+            path: sub_matches
+                .get_many::<(String, String)>("path")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default(),
+        };
 
-        let tag_value: Option<&RequestData> = app_main_request
-            .requests
+        let collections: Vec<AppMainRequest> = match sub_matches.get_one::<String>("right-file") {
+            Some(right_file) => {
+                let Some(right) = load_collection(right_file) else {
+                    return false;
+                };
+                vec![app_main_request, right]
+            }
+            // The same collection, and so the same variables and credentials, on both hosts
+            None => sub_matches
+                .get_many::<String>("base-url")
+                .expect("a side is required")
+                .map(|base_url| AppMainRequest {
+                    base_url: base_url.clone(),
+                    ..app_main_request.clone()
+                })
+                .collect(),
+        };
+        let variables: Vec<VariableStore> = collections
             .iter()
-            .find(|&item| item.req_tag == *tag.unwrap());
-
-        if let Some(request) = tag_value {
-            let client = reqwest::blocking::Client::new();
-            let main_headers = app_main_request.create_header_map(); // Create HeaderMap from the headers
-            let main_variable_dir = app_main_request.variable_dir.clone().unwrap_or_default();
-            let main_variable_access_token_file = app_main_request
-                .variable_access_token_file
-                .clone()
-                .unwrap_or_default();
+            .map(|collection| collection.variables().with_overrides(&var_overrides))
+            .collect();
 
-            let method = &request.req_type;
-            let title = &request.req_title;
-            let params = request.req_params.clone().unwrap_or_default();
+        // Both sides are sent at once, so they see the same backend state
+        let (left, right) = tokio::join!(
+            Side::send(&collections[0], tag, &variables[0], &overrides),
+            Side::send(&collections[1], tag, &variables[1], &overrides)
+        );
+        match (left, right) {
+            (Ok(left), Ok(right)) => vec![left, right],
+            (Err(err), _) | (_, Err(err)) => {
+                display_error(err);
+                return false;
+            }
+        }
+    };
 
-            // let main_url = app_main_request.base_url.clone() + &request.req_end_point;
-            let main_url = format!(
-                "{}{}{}",
-                app_main_request.base_url.clone(),
-                &request.req_end_point,
-                params
-            );
+    let (left, right) = (&sides[0], &sides[1]);
+    display_comparison(left, right) == 0
+}
 
-            let variable_is_save = &request.req_variable_is_save;
-            let variable_type = &request.req_variable_type;
-            let variable_response_value = &request
-                .req_variable_response_value
-                .clone()
-                .unwrap_or_default();
+/// The `history` subcommands
+async fn run_history(action: &str, sub_matches: &ArgMatches) {
+    let file_data = sub_matches
+        .get_one::<String>("file")
+        .expect("file is required");
+    let Some(app_main_request) = load_collection(file_data) else {
+        return;
+    };
+    let run = Run::new(&app_main_request, None);
+    let id = sub_matches.try_get_one::<u64>("id").ok().flatten().copied();
+    let last = sub_matches
+        .try_get_one::<usize>("last")
+        .ok()
+        .flatten()
+        .copied()
+        .unwrap_or(20);
+
+    let entry = match id.map(|id| (id, run.history.find(id))) {
+        Some((_, Ok(Some(entry)))) => Some(entry),
+        Some((id, Ok(None))) => {
+            eprintln!("History entry not found: {}", id);
+            std::process::exit(1);
+        }
+        Some((_, Err(err))) => {
+            display_error(err);
+            return;
+        }
+        None => None,
+    };
 
-            println!("");
-            println!("{} {}", "TITLE    :".blue().bold(), title.green());
-            println!("{} {}", "URL      :".blue().bold(), main_url.yellow());
+    match (action, entry) {
+        ("show", Some(entry)) => entry.display(),
+        ("resend", Some(entry)) => {
+            let var_overrides: Vec<(String, String)> = sub_matches
+                .get_many::<(String, String)>("var")
+                .map(|values| values.cloned().collect())
+                .unwrap_or_default();
+            let variables = app_main_request.variables().with_overrides(&var_overrides);
+            let (jar_path, cookie_jar) = open_cookie_jar(&app_main_request);
 
-            let access_token = format!(
+            println!(
                 "{} {}",
-                variable_type.clone().unwrap_or_default(),
-                read_from_file(
-                    format!("{}/{}", &main_variable_dir, main_variable_access_token_file).as_str()
-                )
-                .unwrap_or_default()
+                "RESEND   :".blue().bold(),
+                format!("#{}", entry.id).purple()
             );
-
-            let response = make_http_request(
-                &client,
-                &method,
-                main_url,
-                main_headers,
-                access_token.trim().to_string(),
-                main_variable_dir,
-                request.req_body.clone(),
+            println!("{} {}", "TITLE    :".blue().bold(), entry.title.green());
+            println!(
+                "{} {} {}",
+                "URL      :".blue().bold(),
+                entry.request.method.blue(),
+                entry.request.url.yellow()
             );
+            let result = run
+                .history
+                .resend(&app_main_request, &entry, &variables, &cookie_jar)
+                .await;
+            close_cookie_jar(&cookie_jar, &jar_path);
 
-            match response {
-                Ok(resp) => {
-                    let status = resp.status();
-                    let body: Value = resp.json().unwrap_or_else(
-                        |_| serde_json::json!({"error": "Failed to parse response as JSON"}),
-                    );
-                    println!("");
+            match result {
+                Ok(captured) => {
+                    println!();
                     println!(
                         "{} {}",
                         "Status   :".blue().bold(),
-                        status.to_string().green()
+                        captured.status.to_string().green()
                     );
                     println!("{}", "Response :".blue().bold());
-                    println!("");
-
-                    if status.is_success()
-                        && variable_is_save.unwrap_or_default()
-                        && !variable_response_value.is_null()
-                    {
-                        let variable_dir = &app_main_request.variable_dir;
-                        let response_value = Value::String(variable_response_value.to_string());
-
-                        let _ = write_to_file(&body, &variable_dir, &response_value);
-                    }
-
-                    display_colored_json(&body, 0); // Display formatted and colored JSON
-                    println!("");
-                    println!("");
+                    println!();
+                    display_colored_json(&variables.mask_json(&captured.body), 0);
+                    println!();
+                    println!();
                 }
+                Err(message) => display_error(message),
+            }
+        }
+        ("clear", _) => match run.history.clear() {
+            Ok(count) => println!("Cleared: {} entries", count.to_string().yellow().bold()),
+            Err(err) => display_error(err),
+        },
+        _ => {
+            let text = sub_matches
+                .try_get_one::<String>("text")
+                .ok()
+                .flatten()
+                .map(String::as_str);
+            let tag = sub_matches
+                .try_get_one::<String>("tag")
+                .ok()
+                .flatten()
+                .map(String::as_str);
+            let found = match run.history.search(tag, text) {
+                Ok(found) => found,
                 Err(err) => {
-                    eprintln!("{}", "Error : ".red().bold());
-                    eprintln!("{}", err.to_string().red());
+                    display_error(err);
+                    return;
                 }
+            };
+            for entry in &found[found.len().saturating_sub(last)..] {
+                entry.display_line();
             }
-        } else {
-            println!("Item not found");
+            println!(
+                "{} {}",
+                "ENTRIES  :".blue().bold(),
+                found.len().to_string().green()
+            );
         }
     }
+}
 
-    fn app_main_request(file_data: &str) -> Result<AppMainRequest, io::Error> {
-        let file_path_buf = expand_tilde(file_data);
-        let file = File::open(file_path_buf).expect("File should open read only");
-        // let file = File::open(file_path_buf).unwrap_or(default);
-        let reader = BufReader::new(file);
-        let app_main_request: AppMainRequest = serde_json::from_reader(reader)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        Ok(app_main_request)
-    }
-
-    fn request_body_data(req_body: RequestDataBody) -> Value {
-        let contents_path = expand_tilde(&req_body.body_file.clone());
-        let body_file_path = contents_path;
-        let body_file = File::open(body_file_path).expect("Failed to read file");
-        let body_reader = BufReader::new(body_file);
-        let body_data: Value =
-            serde_json::from_reader(body_reader).expect("File should be proper JSON");
-        println!("{}", "Request  :".blue().bold());
-        println!("");
-        display_colored_json(&body_data.clone(), 0); // Display formatted and colored JSON
-        println!("");
-        body_data
-    }
-
-    fn handle_get_request(
-        client: &reqwest::blocking::Client,
-        url: String,
-        access_token: String,
-        headers: HeaderMap,
-    ) -> Result<reqwest::blocking::Response, Box<dyn std::error::Error>> {
-        Ok(client
-            .get(url)
-            .header("Authorization", access_token)
-            .headers(headers)
-            .send()?)
-    }
-
-    // fn handle_post_request(
-    //     client: &reqwest::blocking::Client,
-    //     url: String,
-    //     access_token: String,
-    //     variable_dir: String,
-    //     headers: HeaderMap,
-    //     req_body: Option<RequestDataBody>,
-    // ) -> Result<reqwest::blocking::Response, Box<dyn std::error::Error>> {
-    //     match req_body {
-    //         Some(req_body) => {
-    //             // 1. Create the JSON body
-    //             let mut body_data = request_body_data(req_body.clone());
-
-    //             resolve_placeholders(&mut body_data, &variable_dir)?;
-
-    //             // 3. Send
-    //             if req_body.body_type == "FORM_DATA" {
-    //                 Ok(client.post(url).headers(headers)
-    //                     .header("Authorization", access_token)
-    //                     .form(&body_data).send()?)
-    //             } else {
-    //                 let pretty_json_string = serde_json::to_string_pretty(&body_data)?;
-    //                 Ok(client
-    //                     .post(url)
-    //                     .headers(headers)
-    //                     .header("Authorization", access_token)
-    //                     .body(pretty_json_string)
-    //                     // .form(&body_data)
-    //                     .send()?)
-    //             }
-    //         }
-    //         None => Ok(client.post(url).headers(headers).send()?),
-    //     }
-    // }
-
-    fn handle_post_request(
-        client: &reqwest::blocking::Client,
-        url: String,
-        access_token: String,
-        variable_dir: String,
-        headers: HeaderMap,
-        req_body: Option<RequestDataBody>,
-    ) -> Result<reqwest::blocking::Response, Box<dyn std::error::Error>> {
-        match req_body {
-            Some(req_body) => {
-                // 1. Create the base JSON data
-                let mut body_data = request_body_data(req_body.clone());
-                resolve_placeholders(&mut body_data, &variable_dir)?;
-
-                // 2. Check body type and build request
-                match req_body.body_type.as_str() {
-                    "MULTIPART" => {
-                        // Convert JSON payload to a string (this will be one part of the form)
-                        let json_string = serde_json::to_string(&body_data)?;
-
-                        // Start building the multipart form
-                        let mut form = multipart::Form::new();
-
-                        // Add the JSON payload as a text field named 'payload'
-                        form = form.text("payload", json_string);
-
-                        // Add files (if any)
-                        if let Some(file_paths) = req_body.file_paths {
-                            for path_str in file_paths {
-                                let path = Path::new(&path_str);
-                                let filename = path
-                                    .file_name()
-                                    .unwrap_or_default()
-                                    .to_string_lossy()
-                                    .into_owned();
-
-                                // Create a file part from the file bytes
-                                let file = File::open(path)?;
-                                let file_part = multipart::Part::reader(file).file_name(filename);
-
-                                // Add the file part under the field name 'images'
-                                // This corresponds to the 'images' field in your API controller.
-                                form = form.part("images", file_part);
-                            }
-                        }
+/// Send one request, None when no response came back.
+/// The request is saved to the history of `check`, which also validates the body against
+/// its schema and compares it with the snapshot of that name.
+/// `verbose` prints the title, URL, request body and response, or only the body with
+/// `--raw` and `--compact`. `--filter` picks what is printed and compared with the snapshot.
+async fn run_request(
+    app_main_request: &AppMainRequest,
+    request: &RequestData,
+    variables: &VariableStore,
+    cookie_jar: &Option<Arc<CookieStoreMutex>>,
+    overrides: &RequestOverrides,
+    check: (&Run, String),
+    verbose: bool,
+) -> Option<StatusCode> {
+    let (run, name) = check;
+    let labels = verbose && run.format == OutputFormat::Pretty;
+    let resolved = match app_main_request.resolve(request, variables, overrides) {
+        Ok(resolved) => resolved,
+        Err(err) => {
+            display_error(variables.mask_str(&err.to_string()));
+            return None;
+        }
+    };
 
-                        // Send the request with the multipart form
-                        Ok(client
-                            .post(url)
-                            .headers(headers)
-                            .header("Authorization", access_token)
-                            .multipart(form) // Use .multipart() instead of .form()
-                            .send()?)
-                    }
+    if labels {
+        println!();
+        println!(
+            "{} {}",
+            "TITLE    :".blue().bold(),
+            request.req_title.green()
+        );
+        println!(
+            "{} {}",
+            "URL      :".blue().bold(),
+            variables.mask_str(&resolved.url).yellow()
+        );
 
-                    // ----------------------------------------------------
-                    // Existing: Standard form-urlencoded (simple key=value)
-                    // ----------------------------------------------------
-                    "FORM_DATA" => Ok(client
-                        .post(url)
-                        .headers(headers)
-                        .header("Authorization", access_token)
-                        .form(&body_data)
-                        .send()?),
-
-                    // ----------------------------------------------------
-                    // Existing: Standard JSON body
-                    // ----------------------------------------------------
-                    _ => {
-                        // Assume standard JSON body if not specified
-                        let pretty_json_string = serde_json::to_string_pretty(&body_data)?;
-                        Ok(client
-                            .post(url)
-                            .headers(headers)
-                            .header("Authorization", access_token)
-                            .body(pretty_json_string)
-                            .send()?)
-                    }
-                }
-            }
-            None => Ok(client.post(url).headers(headers).send()?),
+        if let Some(body) = &resolved.body {
+            println!("{}", "Request  :".blue().bold());
+            println!();
+            display_colored_json(&variables.mask_json(body), 0); // Display formatted and colored JSON
+            println!();
         }
     }
 
-    fn handle_put_request(
-        client: &reqwest::blocking::Client,
-        url: String,
-        access_token: String,
-        variable_dir: String,
-        headers: HeaderMap,
-        req_body: Option<RequestDataBody>,
-    ) -> Result<reqwest::blocking::Response, Box<dyn std::error::Error>> {
-        match req_body {
-            Some(req_body) => {
-                let mut body_data = request_body_data(req_body.clone());
-
-                resolve_placeholders(&mut body_data, &variable_dir)?;
-
-                if req_body.body_type == "FORM_DATA" {
-                    Ok(client
-                        .put(url)
-                        .headers(headers)
-                        .header("Authorization", access_token)
-                        .form(&body_data)
-                        .send()?)
-                } else {
-                    let pretty_json_string = serde_json::to_string_pretty(&body_data)?;
-                    Ok(client
-                        .put(url)
-                        .headers(headers)
-                        .header("Authorization", access_token)
-                        .body(pretty_json_string)
-                        // .form(&body_data)
-                        .send()?)
-                }
-            }
-            None => Ok(client.put(url).headers(headers).send()?),
+    let captured = match run
+        .history
+        .execute(
+            app_main_request,
+            request,
+            &resolved,
+            variables,
+            cookie_jar,
+            overrides,
+        )
+        .await
+    {
+        Ok(captured) => captured,
+        Err(message) => {
+            display_error(message);
+            return None;
         }
+    };
+
+    if labels {
+        println!();
+        println!(
+            "{} {}",
+            "Status   :".blue().bold(),
+            captured.status.to_string().green()
+        );
+        println!("{}", "Response :".blue().bold());
+        println!();
     }
 
-    fn handle_delete_request(
-        client: &reqwest::blocking::Client,
-        url: String,
-        access_token: String,
-        variable_dir: String,
-        headers: HeaderMap,
-        req_body: Option<RequestDataBody>,
-    ) -> Result<reqwest::blocking::Response, Box<dyn std::error::Error>> {
-        match req_body {
-            Some(req_body) => {
-                let mut body_data = request_body_data(req_body.clone());
-
-                resolve_placeholders(&mut body_data, &variable_dir)?;
-                if req_body.body_type == "FORM_DATA" {
-                    // Simplified: Just send the DELETE request without a body here.
-                    Ok(client
-                        .delete(url) // Changed from .put(url)
-                        .headers(headers)
-                        .header("Authorization", access_token)
-                        .send()?)
-                } else {
-                    let pretty_json_string = serde_json::to_string_pretty(&body_data)?;
-                    // Use .body() to send the JSON content (non-standard for DELETE)
-                    Ok(client
-                        .delete(url) // Changed from .put(url)
-                        .headers(headers)
-                        .header("Authorization", access_token)
-                        .body(pretty_json_string)
-                        .send()?)
-                }
-            }
-            // If there is no body, it is a standard DELETE request.
-            None => Ok(client
-                .delete(url) // Changed from .put(url)
-                .headers(headers)
-                .header("Authorization", access_token)
-                .send()?),
+    let body = variables.mask_json(&captured.json_or_text());
+    let output = match &run.filter {
+        Some(filter) => filter.apply(&body).map_err(display_error).ok(),
+        None => Some(vec![body]),
+    };
+    match (&output, run.format) {
+        _ if !verbose => {}
+        (None, _) => {}
+        (Some(_), OutputFormat::Pretty) if run.filter.is_none() => {
+            display_colored_json(&variables.mask_json(&captured.body), 0); // Display formatted and colored JSON
+            println!();
+            println!();
         }
+        (Some(values), format) => display_output(values, format),
     }
 
-    fn make_http_request(
-        client: &reqwest::blocking::Client,
-        method: &str,
-        url: String,
-        headers: HeaderMap,
-        access_token: String,
-        variable_dir: String,
-        req_body: Option<RequestDataBody>,
-    ) -> Result<reqwest::blocking::Response, Box<dyn std::error::Error>> {
-        match method {
-            "GET" => handle_get_request(client, url, access_token, headers),
-            "POST" => {
-                handle_post_request(client, url, access_token, variable_dir, headers, req_body)
+    match app_main_request.validate(request, &captured) {
+        Ok(Some(mut violations)) => {
+            // A reason quotes the value, which may be a secret
+            for violation in &mut violations {
+                violation.reason = variables.mask_str(&violation.reason);
+            }
+            display_schema_violations(&violations);
+            if !violations.is_empty() {
+                run.schema_failures.fetch_add(1, Ordering::Relaxed);
             }
-            "PUT" => handle_put_request(client, url, access_token, variable_dir, headers, req_body),
-            "DELETE" => handle_delete_request(client, url, access_token, variable_dir, headers, req_body),
-            _ => Err(format!("Unsupported HTTP method: {}", method).into()),
+        }
+        Ok(None) => {}
+        Err(err) => {
+            display_error(err);
+            run.schema_failures.fetch_add(1, Ordering::Relaxed);
         }
     }
 
-    /// Scans a JSON object for strings like "{{VAR_NAME}}" and replaces them
-    /// with the content of ./variables/VAR_NAME.txt
-    fn resolve_placeholders(
-        json_body: &mut Value,
-        variable_dir: &String,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // We only look inside JSON Objects (Key-Value pairs)
-        if let Value::Object(ref mut map) = json_body {
-            for (_key, value) in map {
-                // We only care if the value is a String
-                if let Value::String(val_str) = value {
-                    if val_str.starts_with("{{") && val_str.ends_with("}}") {
-                        // 1. Extract variable name (e.g., "REFRESH_TOKEN")
-                        let var_name = &val_str[2..val_str.len() - 2];
-
-                        // 2. Build path
-                        // let file_path = format!("./variables/{}.txt", var_name);
-                        let file_path = format!("{}/{}.txt", variable_dir, var_name);
-
-                        // 3. Read file
-                        let content = read_from_file(&file_path.as_str()).map_err(|e| {
-                            format!("Error reading variable '{}': {}", file_path, e)
-                        })?;
-
-                        // 4. Update the JSON value directly
-                        *val_str = content.trim().to_string();
-                    }
-                }
-            }
+    // Secrets are saved masked, snapshots are meant to be committed
+    if let (Some(snapshots), Some(mut values)) = (&run.snapshots, output) {
+        let config = app_main_request.snapshot_config(request);
+        let body = match values.len() {
+            1 => values.remove(0),
+            _ => Value::Array(values),
+        };
+        if let Err(err) = snapshots.check(&config, &name, &body) {
+            display_error(err);
         }
-        Ok(())
     }
+    Some(captured.status)
 }
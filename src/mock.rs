@@ -0,0 +1,832 @@
+use chrono::Utc;
+use colored::Colorize;
+use percent_encoding::percent_decode_str;
+use rand::Rng;
+use regex::{Captures, Regex};
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::collection::AppMainRequest;
+use crate::file_ops::{expand_tilde, get_nested_value, read_from_file, resolve_relative};
+use crate::path_params::{segment_parts, SegmentPart};
+
+/// Larger request bodies are refused
+const MAX_BODY: usize = 10 * 1024 * 1024;
+
+/// `{{name}}` inside an example file or a mock header
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([^{}]+?)\s*\}\}").expect("valid placeholder regex"));
+
+/// Canned response served by `mock` for a request, set with `"mock": { ... }`
+///
+/// `example_file` is read on every hit, so editing it changes the next response.
+/// `{{path.id}}`, `{{query.page}}`, `{{header.X-Name}}`, `{{body.user.name}}`,
+/// `{{now}}` and `{{uuid}}` inside it are filled from the incoming request.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MockConfig {
+    #[serde(default = "default_status")]
+    status: u16,
+    example_file: Option<String>,
+    #[serde(default)]
+    delay_ms: u64,
+    #[serde(default)]
+    headers: HashMap<String, MockHeaderValue>,
+}
+
+/// A header value, or a list for a header sent several times such as `Set-Cookie`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum MockHeaderValue {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl MockHeaderValue {
+    fn values(&self) -> &[String] {
+        match self {
+            MockHeaderValue::One(value) => std::slice::from_ref(value),
+            MockHeaderValue::Many(values) => values,
+        }
+    }
+}
+
+fn default_status() -> u16 {
+    200
+}
+
+impl MockConfig {
+    /// Look a relative `example_file` up next to the collection first
+    pub(crate) fn resolve_files(&mut self, collection_dir: &Path) {
+        if let Some(example_file) = &self.example_file {
+            self.example_file = Some(resolve_relative(collection_dir, example_file));
+        }
+    }
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        MockConfig {
+            status: default_status(),
+            example_file: None,
+            delay_ms: 0,
+            headers: HashMap::new(),
+        }
+    }
+}
+
+enum Segment {
+    Literal(String),
+    /// A segment with parameters, e.g. `:id` or `v{version}`, matched by `regex`
+    Pattern {
+        text: String,
+        regex: Regex,
+        names: Vec<String>,
+    },
+}
+
+/// One `RequestData` as the mock server sees it
+struct MockRoute {
+    tag: String,
+    method: String,
+    segments: Vec<Segment>,
+    /// The sorted `req_query` pairs, only compared when replaying
+    query: Vec<(String, String)>,
+    mock: MockConfig,
+}
+
+/// A request read from a client of `mock`, `replay` or `record`, header names are lowercase
+pub(crate) struct IncomingRequest {
+    pub(crate) method: String,
+    pub(crate) target: String,
+    pub(crate) headers: HashMap<String, String>,
+    pub(crate) body: Vec<u8>,
+}
+
+pub(crate) struct MockResponse {
+    pub(crate) status: StatusCode,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+/// Turn `/users/:id`, `/users/{id}`, `/v{version}` and `/users/{{USER_ID}}` into path segments,
+/// split the same way `fill_path_params` fills them
+fn parse_segments(prefix: &str, end_point: &str) -> Vec<Segment> {
+    let path = end_point.split('?').next().unwrap_or_default();
+    format!("{}{}", prefix, path)
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            let mut pattern = String::from("^");
+            let mut names = Vec::new();
+            for part in segment_parts(segment) {
+                let name = match part {
+                    SegmentPart::Text(text) => {
+                        pattern.push_str(&regex::escape(text));
+                        continue;
+                    }
+                    SegmentPart::Param(name) => name,
+                    SegmentPart::Placeholder(placeholder) => placeholder
+                        .trim_start_matches('{')
+                        .trim_end_matches('}')
+                        .trim(),
+                };
+                pattern.push_str("(.+?)");
+                names.push(name.to_string());
+            }
+            pattern.push('$');
+
+            match Regex::new(&pattern) {
+                Ok(regex) if !names.is_empty() => Segment::Pattern {
+                    text: segment.to_string(),
+                    regex,
+                    names,
+                },
+                _ => Segment::Literal(segment.to_string()),
+            }
+        })
+        .collect()
+}
+
+/// The `req_query` values as sorted pairs, an array gives one pair per item
+pub(crate) fn query_pairs(req_query: &Option<Map<String, Value>>) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    for (name, value) in req_query.iter().flatten() {
+        let values = match value {
+            Value::Array(items) => items.clone(),
+            other => vec![other.clone()],
+        };
+        for value in values {
+            let value = match value {
+                Value::String(text) => text,
+                other => other.to_string(),
+            };
+            pairs.push((name.clone(), value));
+        }
+    }
+    pairs.sort();
+    pairs
+}
+
+impl MockRoute {
+    /// The path parameters when `path` matches this route
+    fn matches(&self, path: &[String]) -> Option<HashMap<String, String>> {
+        if path.len() != self.segments.len() {
+            return None;
+        }
+
+        let mut params = HashMap::new();
+        for (segment, value) in self.segments.iter().zip(path) {
+            match segment {
+                Segment::Literal(literal) if literal == value => {}
+                Segment::Literal(_) => return None,
+                Segment::Pattern { regex, names, .. } => {
+                    let captures = regex.captures(value)?;
+                    for (name, capture) in names.iter().zip(captures.iter().skip(1)) {
+                        params.insert(name.clone(), capture?.as_str().to_string());
+                    }
+                }
+            }
+        }
+        Some(params)
+    }
+
+    fn param_count(&self) -> usize {
+        self.segments
+            .iter()
+            .filter(|segment| matches!(segment, Segment::Pattern { .. }))
+            .count()
+    }
+}
+
+/// Serves the method and endpoint of every request of a collection
+pub struct MockServer {
+    routes: Vec<MockRoute>,
+    /// Match the query too and serve the example files as they are, for `replay`
+    replay: bool,
+}
+
+impl MockServer {
+    /// One route per request, under the path of `base_url`
+    pub fn new(app_main_request: &AppMainRequest) -> MockServer {
+        let prefix = Url::parse(&app_main_request.base_url)
+            .map(|url| url.path().trim_end_matches('/').to_string())
+            .unwrap_or_default();
+
+        let routes = app_main_request
+            .requests
+            .iter()
+            .map(|request| MockRoute {
+                tag: request.req_tag.clone(),
+                method: request.req_type.to_uppercase(),
+                segments: parse_segments(&prefix, &request.req_end_point),
+                query: query_pairs(&request.req_query),
+                mock: request.mock.clone().unwrap_or_default(),
+            })
+            .collect();
+        MockServer {
+            routes,
+            replay: false,
+        }
+    }
+
+    /// Serve a collection written by `record`: a request only matches with the same query,
+    /// and the recorded responses are sent back byte for byte
+    pub fn replay(app_main_request: &AppMainRequest) -> MockServer {
+        MockServer {
+            replay: true,
+            ..MockServer::new(app_main_request)
+        }
+    }
+
+    /// Print the routes and answer requests until the process is stopped
+    pub async fn serve(self, host: &str, port: u16) -> io::Result<()> {
+        self.serve_on(TcpListener::bind((host, port)).await?).await
+    }
+
+    /// `serve` on a listener that is already bound
+    pub async fn serve_on(self, listener: TcpListener) -> io::Result<()> {
+        let label = if self.replay {
+            "REPLAY   :"
+        } else {
+            "MOCK     :"
+        };
+        println!(
+            "{} http://{}",
+            label.blue().bold(),
+            listener.local_addr()?.to_string().green()
+        );
+        for route in &self.routes {
+            let path = route
+                .segments
+                .iter()
+                .map(|segment| match segment {
+                    Segment::Literal(text) | Segment::Pattern { text, .. } => text.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            let query = route
+                .query
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect::<Vec<_>>()
+                .join("&");
+            let path = if query.is_empty() || !self.replay {
+                path
+            } else {
+                format!("{}?{}", path, query)
+            };
+            let example = route.mock.example_file.as_deref().unwrap_or("{}");
+            println!(
+                "  {:<7} /{} -> {} {} {}",
+                route.method.yellow(),
+                path,
+                route.mock.status,
+                example,
+                format!("({})", route.tag).purple()
+            );
+        }
+        println!();
+
+        let server = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let server = Arc::clone(&server);
+            tokio::spawn(async move {
+                if let Err(err) = server.handle(stream).await {
+                    eprintln!("{}", format!("Mock connection failed: {}", err).red());
+                }
+            });
+        }
+    }
+
+    /// Answer one request, the connection is closed afterwards
+    async fn handle(&self, stream: TcpStream) -> io::Result<()> {
+        let mut stream = BufReader::new(stream);
+        let Some(request) = read_request(&mut stream).await? else {
+            return Ok(());
+        };
+
+        let (response, tag, delay) = self.respond(&request);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+
+        log_request("REQUEST  :", &request, response.status, tag);
+        write_response(stream.get_mut(), &response).await
+    }
+
+    /// The response for a request, the tag of the route and how long to wait first
+    fn respond(&self, request: &IncomingRequest) -> (MockResponse, Option<&str>, Duration) {
+        let url = match Url::parse(&format!("http://mock{}", request.target)) {
+            Ok(url) => url,
+            Err(err) => {
+                return (
+                    error_response(StatusCode::BAD_REQUEST, &err),
+                    None,
+                    Duration::ZERO,
+                )
+            }
+        };
+
+        // Browsers ask before cross-origin calls from a frontend dev server
+        if request.method == "OPTIONS" {
+            let response = MockResponse {
+                status: StatusCode::NO_CONTENT,
+                headers: cors_headers(),
+                body: Vec::new(),
+            };
+            return (response, None, Duration::ZERO);
+        }
+
+        let path: Vec<String> = url
+            .path()
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+            .collect();
+
+        let mut query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        query.sort();
+
+        // Literal segments win over parameters, e.g. /users/me before /users/:id
+        let matched = self
+            .routes
+            .iter()
+            .filter(|route| route.method == request.method)
+            .filter(|route| !self.replay || route.query == query)
+            .filter_map(|route| route.matches(&path).map(|params| (route, params)))
+            .min_by_key(|(route, _)| route.param_count());
+        let Some((route, params)) = matched else {
+            let kind = if self.replay { "recording" } else { "mock" };
+            let message = format!("No {} for {} {}", kind, request.method, request.target);
+            return (
+                error_response(StatusCode::NOT_FOUND, &message),
+                None,
+                Duration::ZERO,
+            );
+        };
+
+        let response = match route.render(request, &url, &params, self.replay) {
+            Ok(response) => response,
+            Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &err),
+        };
+        (
+            response,
+            Some(&route.tag),
+            Duration::from_millis(route.mock.delay_ms),
+        )
+    }
+}
+
+impl MockRoute {
+    /// The example file with its placeholders filled, or `{}` without one.
+    /// `replay` sends the file as it is.
+    fn render(
+        &self,
+        request: &IncomingRequest,
+        url: &Url,
+        params: &HashMap<String, String>,
+        replay: bool,
+    ) -> io::Result<MockResponse> {
+        let status = StatusCode::from_u16(self.mock.status)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let fill = |template: &str| {
+            if replay {
+                template.to_string()
+            } else {
+                fill_template(template, request, url, params)
+            }
+        };
+
+        let (body, is_json) = match &self.mock.example_file {
+            Some(example_file) if replay => {
+                let body = std::fs::read(expand_tilde(example_file))?;
+                let is_json = serde_json::from_slice::<Value>(&body).is_ok();
+                (body, is_json)
+            }
+            Some(example_file) => {
+                let body = fill(&read_from_file(example_file)?);
+                let is_json = Path::new(example_file)
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+                    || serde_json::from_str::<Value>(&body).is_ok();
+                (body.into_bytes(), is_json)
+            }
+            None => (b"{}".to_vec(), true),
+        };
+
+        let mut headers = cors_headers();
+        let content_type = if is_json {
+            "application/json"
+        } else {
+            "text/plain; charset=utf-8"
+        };
+        headers.push(("Content-Type".to_string(), content_type.to_string()));
+
+        // The configured headers replace the defaults of the same name
+        for (name, value) in &self.mock.headers {
+            headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+            for value in value.values() {
+                headers.push((name.clone(), fill(value)));
+            }
+        }
+
+        Ok(MockResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// Read the request line, the headers and a `Content-Length` or chunked body
+pub(crate) async fn read_request(
+    stream: &mut BufReader<TcpStream>,
+) -> io::Result<Option<IncomingRequest>> {
+    let mut request_line = String::new();
+    if stream.read_line(&mut request_line).await? == 0 {
+        return Ok(None);
+    }
+
+    // e.g. "GET /users/1?page=2 HTTP/1.1"
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_uppercase();
+    let target = parts.next().unwrap_or("/").to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if stream.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let is_chunked = headers
+        .get("transfer-encoding")
+        .is_some_and(|encoding| encoding.to_lowercase().contains("chunked"));
+    let body = if is_chunked {
+        read_chunked_body(stream).await?
+    } else {
+        let length: usize = headers
+            .get("content-length")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or_default();
+        check_body_size(length)?;
+        let mut body = vec![0u8; length];
+        stream.read_exact(&mut body).await?;
+        body
+    };
+
+    Ok(Some(IncomingRequest {
+        method,
+        target,
+        headers,
+        body,
+    }))
+}
+
+/// Read `SIZE\r\nDATA\r\n` chunks up to the empty last one and its trailers
+async fn read_chunked_body(stream: &mut BufReader<TcpStream>) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let mut size_line = String::new();
+        stream.read_line(&mut size_line).await?;
+        // A size can be followed by `;extension`
+        let size = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid chunk size '{}'", size),
+            )
+        })?;
+        if size == 0 {
+            break;
+        }
+        check_body_size(body.len() + size)?;
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        stream.read_exact(&mut body[start..]).await?;
+        let mut line_end = String::new();
+        stream.read_line(&mut line_end).await?;
+    }
+
+    loop {
+        let mut trailer = String::new();
+        if stream.read_line(&mut trailer).await? == 0 || trailer.trim().is_empty() {
+            return Ok(body);
+        }
+    }
+}
+
+fn check_body_size(length: usize) -> io::Result<()> {
+    if length > MAX_BODY {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Request body of {} bytes is too large", length),
+        ));
+    }
+    Ok(())
+}
+
+/// Write a whole response, the connection is closed afterwards
+pub(crate) async fn write_response(
+    stream: &mut TcpStream,
+    response: &MockResponse,
+) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\n",
+        response.status.as_u16(),
+        response.status.canonical_reason().unwrap_or_default()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str(&format!(
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    ));
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.flush().await
+}
+
+/// Print one line per served request, with the tag of the request that answered it
+pub(crate) fn log_request(
+    label: &str,
+    request: &IncomingRequest,
+    status: StatusCode,
+    tag: Option<&str>,
+) {
+    let code = status.as_u16().to_string();
+    println!(
+        "{} {} {} {}{}",
+        label.blue().bold(),
+        request.method.yellow(),
+        request.target,
+        if status.is_success() {
+            code.green()
+        } else {
+            code.red()
+        },
+        tag.map(|tag| format!(" ({})", tag))
+            .unwrap_or_default()
+            .purple()
+    );
+}
+
+/// Replace `{{path.X}}`, `{{query.X}}`, `{{header.X}}`, `{{body.X}}`, `{{now}}` and `{{uuid}}`,
+/// anything else is left as it is
+fn fill_template(
+    template: &str,
+    request: &IncomingRequest,
+    url: &Url,
+    params: &HashMap<String, String>,
+) -> String {
+    let body = request_body(request);
+
+    PLACEHOLDER
+        .replace_all(template, |captures: &Captures| {
+            let name = &captures[1];
+            let value = match name.split_once('.') {
+                Some(("path", key)) => params.get(key).cloned(),
+                Some(("query", key)) => url
+                    .query_pairs()
+                    .find(|(name, _)| name == key)
+                    .map(|(_, value)| value.into_owned()),
+                Some(("header", key)) => request.headers.get(&key.to_lowercase()).cloned(),
+                Some(("body", key)) => get_nested_value(&body, key).map(|value| match value {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                }),
+                _ if name == "now" => Some(Utc::now().to_rfc3339()),
+                _ if name == "uuid" => Some(random_uuid()),
+                _ => None,
+            };
+            value.unwrap_or_else(|| captures[0].to_string())
+        })
+        .into_owned()
+}
+
+/// The request body as JSON, a form body becomes an object of its fields
+fn request_body(request: &IncomingRequest) -> Value {
+    if let Ok(body) = serde_json::from_slice(&request.body) {
+        return body;
+    }
+
+    let body = String::from_utf8_lossy(&request.body);
+    let form: Map<String, Value> = Url::parse(&format!("http://mock/?{}", body))
+        .map(|url| {
+            url.query_pairs()
+                .map(|(name, value)| (name.into_owned(), Value::String(value.into_owned())))
+                .collect()
+        })
+        .unwrap_or_default();
+    Value::Object(form)
+}
+
+fn random_uuid() -> String {
+    let mut bytes: [u8; 16] = rand::thread_rng().gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = hex::encode(bytes);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn cors_headers() -> Vec<(String, String)> {
+    vec![
+        ("Access-Control-Allow-Origin".to_string(), "*".to_string()),
+        (
+            "Access-Control-Allow-Methods".to_string(),
+            "GET, POST, PUT, DELETE, OPTIONS".to_string(),
+        ),
+        ("Access-Control-Allow-Headers".to_string(), "*".to_string()),
+    ]
+}
+
+fn error_response(status: StatusCode, message: &impl ToString) -> MockResponse {
+    let mut headers = cors_headers();
+    headers.push(("Content-Type".to_string(), "application/json".to_string()));
+    MockResponse {
+        status,
+        headers,
+        body: serde_json::json!({ "error": message.to_string() })
+            .to_string()
+            .into_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn server(requests: Value) -> MockServer {
+        let collection: AppMainRequest = serde_json::from_value(json!({
+            "base_url": "http://localhost:8080/api",
+            "requests": requests
+        }))
+        .unwrap();
+        MockServer::new(&collection)
+    }
+
+    fn request(
+        method: &str,
+        target: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> IncomingRequest {
+        IncomingRequest {
+            method: method.to_string(),
+            target: target.to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_lowercase(), value.to_string()))
+                .collect(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn header<'a>(response: &'a MockResponse, name: &str) -> Vec<&'a str> {
+        response
+            .headers
+            .iter()
+            .filter(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    fn example_file(name: &str, contents: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("rest_api_mock_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn literal_routes_win_over_param_routes() {
+        let server = server(json!([
+            {"req_tag": "user_get", "req_title": "", "req_type": "GET", "req_end_point": "/users/:id"},
+            {"req_tag": "user_me", "req_title": "", "req_type": "get", "req_end_point": "/users/me"},
+            {"req_tag": "user_delete", "req_title": "", "req_type": "DELETE", "req_end_point": "/users/{id}"},
+            {"req_tag": "item_get", "req_title": "", "req_type": "GET", "req_end_point": "/v{version}/items/{id}.json?full=1"}
+        ]));
+        let tag = |method: &str, target: &str| {
+            let (response, tag, _) = server.respond(&request(method, target, &[], ""));
+            (response.status.as_u16(), tag.map(str::to_string))
+        };
+
+        assert_eq!(
+            tag("GET", "/api/users/me"),
+            (200, Some("user_me".to_string()))
+        );
+        assert_eq!(
+            tag("GET", "/api/users/42"),
+            (200, Some("user_get".to_string()))
+        );
+        assert_eq!(
+            tag("DELETE", "/api/users/42"),
+            (200, Some("user_delete".to_string()))
+        );
+        assert_eq!(
+            tag("GET", "/api/v2/items/7.json"),
+            (200, Some("item_get".to_string()))
+        );
+        assert_eq!(tag("GET", "/api/v2/items/7.xml"), (404, None));
+        assert_eq!(tag("GET", "/users/42"), (404, None));
+        assert_eq!(tag("POST", "/api/users/42"), (404, None));
+        assert_eq!(tag("GET", "/api/users/42/posts"), (404, None));
+    }
+
+    #[test]
+    fn example_placeholders_are_filled_from_the_request() {
+        let example = example_file(
+            "order.json",
+            r#"{"id": "{{path.id}}", "version": "{{ path.version }}", "page": "{{query.page}}",
+                "name": "{{body.user.name}}", "agent": "{{header.X-Agent}}", "plan": "{{body.plan}}", "keep": "{{other}}"}"#,
+        );
+        let server = server(json!([{
+            "req_tag": "order_update", "req_title": "", "req_type": "PUT",
+            "req_end_point": "/v{version}/orders/:id",
+            "mock": {"example_file": example}
+        }]));
+
+        let incoming = request(
+            "PUT",
+            "/api/v3/orders/a%20b?page=2",
+            &[("X-Agent", "tests")],
+            r#"{"user": {"name": "Ann"}, "plan": "pro"}"#,
+        );
+        let (response, _, _) = server.respond(&incoming);
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(
+            body,
+            json!({"id": "a b", "version": "3", "page": "2", "name": "Ann", "agent": "tests", "plan": "pro", "keep": "{{other}}"})
+        );
+        assert_eq!(header(&response, "Content-Type"), ["application/json"]);
+
+        // A form body is read as an object of its fields, a missing value keeps its placeholder
+        let form = request("PUT", "/api/v3/orders/1", &[], "plan=basic+plus");
+        let (response, _, _) = server.respond(&form);
+        let body: Value = serde_json::from_slice(&response.body).unwrap();
+        assert_eq!(body["plan"], "basic plus");
+        assert_eq!(body["name"], "{{body.user.name}}");
+        assert_eq!(body["page"], "{{query.page}}");
+    }
+
+    #[test]
+    fn status_delay_and_headers_come_from_the_mock_config() {
+        let server = server(json!([{
+            "req_tag": "user_create", "req_title": "", "req_type": "POST", "req_end_point": "/users/:id",
+            "mock": {
+                "status": 201,
+                "delay_ms": 250,
+                "headers": {"content-type": "application/hal+json", "Location": "/users/{{path.id}}"}
+            }
+        }]));
+
+        let (response, tag, delay) = server.respond(&request("POST", "/api/users/9", &[], ""));
+        assert_eq!(response.status, StatusCode::CREATED);
+        assert_eq!(tag, Some("user_create"));
+        assert_eq!(delay, Duration::from_millis(250));
+        assert_eq!(response.body, b"{}");
+        assert_eq!(header(&response, "Content-Type"), ["application/hal+json"]);
+        assert_eq!(header(&response, "Location"), ["/users/9"]);
+        assert_eq!(header(&response, "Access-Control-Allow-Origin"), ["*"]);
+    }
+
+    #[test]
+    fn options_requests_get_the_cors_headers() {
+        let server = server(json!([]));
+        let (response, tag, delay) = server.respond(&request("OPTIONS", "/api/anything", &[], ""));
+        assert_eq!(response.status, StatusCode::NO_CONTENT);
+        assert_eq!((tag, delay), (None, Duration::ZERO));
+        assert!(response.body.is_empty());
+        assert_eq!(header(&response, "Access-Control-Allow-Origin"), ["*"]);
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Methods"),
+            ["GET, POST, PUT, DELETE, OPTIONS"]
+        );
+        assert_eq!(header(&response, "Access-Control-Allow-Headers"), ["*"]);
+    }
+}
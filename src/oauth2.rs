@@ -0,0 +1,580 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use colored::Colorize;
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+use crate::variables::VariableStore;
+
+/// Cached tokens are renewed this many seconds before they actually expire
+const EXPIRY_MARGIN_SECS: u64 = 30;
+
+/// One lock per cached token, so concurrent requests wait for a single token
+/// request instead of each fetching their own or binding the same redirect port
+static TOKEN_LOCKS: LazyLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(Mutex::default);
+
+/// OAuth2 token settings, used through `"auth": { "type": "oauth2", ... }`
+#[derive(Clone, Debug, Deserialize)]
+pub struct OAuth2Config {
+    grant_type: OAuth2GrantType,
+    token_url: String,
+    authorize_url: Option<String>,
+    client_id: String,
+    client_secret: Option<String>,
+    scope: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    #[serde(default = "default_redirect_port")]
+    redirect_port: u16,
+    #[serde(default = "default_token_name")]
+    token_name: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OAuth2GrantType {
+    ClientCredentials,
+    Password,
+    AuthorizationCode,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+fn default_redirect_port() -> u16 {
+    8765
+}
+
+fn default_token_name() -> String {
+    "OAUTH2_ACCESS_TOKEN".to_string()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+impl OAuth2Config {
+    /// Replace "{{VAR_NAME}}" placeholders in the credential fields
+    pub fn resolve(
+        &self,
+        variables: &VariableStore,
+    ) -> Result<OAuth2Config, Box<dyn std::error::Error>> {
+        let resolve = |text: &Option<String>| {
+            text.as_deref()
+                .map(|text| variables.resolve_str(text))
+                .transpose()
+        };
+
+        Ok(OAuth2Config {
+            token_url: variables.resolve_str(&self.token_url)?,
+            authorize_url: resolve(&self.authorize_url)?,
+            client_id: variables.resolve_str(&self.client_id)?,
+            client_secret: resolve(&self.client_secret)?,
+            username: resolve(&self.username)?,
+            password: resolve(&self.password)?,
+            ..self.clone()
+        })
+    }
+
+    /// Return a valid access token: the cached one while it has not expired,
+    /// otherwise a refreshed one, otherwise one from a fresh grant
+    pub async fn access_token(
+        &self,
+        client: &Client,
+        variables: &VariableStore,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let lock = TOKEN_LOCKS
+            .lock()
+            .map_err(|_| "OAuth2 token lock poisoned")?
+            .entry(self.token_name.clone())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().await;
+
+        let expires_at_name = format!("{}_EXPIRES_AT", self.token_name);
+        let refresh_name = format!("{}_REFRESH_TOKEN", self.token_name);
+
+        let cached_token = variables.get(&self.token_name).unwrap_or_default();
+        let expires_at = variables
+            .get(&expires_at_name)
+            .ok()
+            .and_then(|value| value.parse::<u64>().ok());
+
+        if !cached_token.is_empty() {
+            match expires_at {
+                Some(expires_at) if expires_at > unix_now() + EXPIRY_MARGIN_SECS => {
+                    return Ok(cached_token)
+                }
+                None => return Ok(cached_token),
+                _ => {}
+            }
+        }
+
+        let refresh_token = variables.get(&refresh_name).unwrap_or_default();
+        let token = if refresh_token.is_empty() {
+            self.request_grant(client).await?
+        } else {
+            eprintln!(
+                "{} {}",
+                "OAuth2   :".blue().bold(),
+                "refreshing token".green()
+            );
+            let form = [
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token.as_str()),
+            ];
+            match self.request_token(client, &form).await {
+                Ok(token) => token,
+                Err(err) => {
+                    eprintln!("{}", format!("Token refresh failed: {}", err).red());
+                    self.request_grant(client).await?
+                }
+            }
+        };
+
+        let expires_at = token
+            .expires_in
+            .map(|expires_in| (unix_now() + expires_in).to_string())
+            .unwrap_or_default();
+        variables.set(&self.token_name, &token.access_token)?;
+        variables.set(&expires_at_name, &expires_at)?;
+        if let Some(refresh_token) = &token.refresh_token {
+            variables.set(&refresh_name, refresh_token)?;
+        }
+
+        Ok(token.access_token)
+    }
+
+    /// Run the configured grant to get a brand new token
+    async fn request_grant(
+        &self,
+        client: &Client,
+    ) -> Result<TokenResponse, Box<dyn std::error::Error>> {
+        eprintln!(
+            "{} {}",
+            "OAuth2   :".blue().bold(),
+            format!("requesting token from {}", self.token_url).green()
+        );
+
+        match self.grant_type {
+            OAuth2GrantType::ClientCredentials => {
+                self.request_token(client, &[("grant_type", "client_credentials")])
+                    .await
+            }
+            OAuth2GrantType::Password => {
+                let username = self
+                    .username
+                    .as_deref()
+                    .ok_or("OAuth2 password grant needs a username")?;
+                let password = self
+                    .password
+                    .as_deref()
+                    .ok_or("OAuth2 password grant needs a password")?;
+                let form = [
+                    ("grant_type", "password"),
+                    ("username", username),
+                    ("password", password),
+                ];
+                self.request_token(client, &form).await
+            }
+            OAuth2GrantType::AuthorizationCode => self.authorization_code(client).await,
+        }
+    }
+
+    /// Authorization code with PKCE, the code comes back on a localhost redirect
+    async fn authorization_code(
+        &self,
+        client: &Client,
+    ) -> Result<TokenResponse, Box<dyn std::error::Error>> {
+        let code_verifier = random_string(64);
+        let state = random_string(16);
+        let url = self.authorize_request(&code_verifier, &state)?;
+
+        let listener = TcpListener::bind(("127.0.0.1", self.redirect_port)).await?;
+        eprintln!(
+            "{} {}",
+            "OAuth2   :".blue().bold(),
+            "open this URL in your browser to sign in".green()
+        );
+        eprintln!("{}", url.as_str().yellow());
+
+        self.exchange_code(client, &listener, &code_verifier, &state)
+            .await
+    }
+
+    /// The authorize URL to open in the browser, with the S256 challenge of `code_verifier`
+    fn authorize_request(
+        &self,
+        code_verifier: &str,
+        state: &str,
+    ) -> Result<Url, Box<dyn std::error::Error>> {
+        let authorize_url = self
+            .authorize_url
+            .as_deref()
+            .ok_or("OAuth2 authorization_code grant needs an authorize_url")?;
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let mut url = Url::parse(authorize_url)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri())
+            .append_pair("state", state)
+            .append_pair("code_challenge", &code_challenge)
+            .append_pair("code_challenge_method", "S256");
+        if let Some(scope) = &self.scope {
+            url.query_pairs_mut().append_pair("scope", scope);
+        }
+        Ok(url)
+    }
+
+    fn redirect_uri(&self) -> String {
+        format!("http://127.0.0.1:{}/callback", self.redirect_port)
+    }
+
+    /// Wait for the redirect, check its state and trade the code for a token
+    async fn exchange_code(
+        &self,
+        client: &Client,
+        listener: &TcpListener,
+        code_verifier: &str,
+        state: &str,
+    ) -> Result<TokenResponse, Box<dyn std::error::Error>> {
+        let params = wait_for_redirect(listener).await?;
+        if let Some(error) = params.get("error") {
+            return Err(format!("OAuth2 authorization failed: {}", error).into());
+        }
+        if params.get("state").map(String::as_str) != Some(state) {
+            return Err("OAuth2 redirect state does not match".into());
+        }
+        let code = params
+            .get("code")
+            .ok_or("OAuth2 redirect has no authorization code")?;
+
+        let redirect_uri = self.redirect_uri();
+        let form = [
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        self.request_token(client, &form).await
+    }
+
+    /// POST a form to the token endpoint, adding the client credentials and scope
+    async fn request_token(
+        &self,
+        client: &Client,
+        form: &[(&str, &str)],
+    ) -> Result<TokenResponse, Box<dyn std::error::Error>> {
+        let mut params: Vec<(&str, &str)> = form.to_vec();
+        params.push(("client_id", &self.client_id));
+        if let Some(client_secret) = &self.client_secret {
+            params.push(("client_secret", client_secret));
+        }
+        if let Some(scope) = &self.scope {
+            params.push(("scope", scope));
+        }
+
+        let response = client.post(&self.token_url).form(&params).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Token endpoint returned {}: {}", status, body).into());
+        }
+
+        Ok(response.json().await?)
+    }
+}
+
+/// Wait for the browser to hit the redirect URI and return its query parameters
+async fn wait_for_redirect(
+    listener: &TcpListener,
+) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let mut stream = BufReader::new(stream);
+        let mut request_line = String::new();
+        stream.read_line(&mut request_line).await?;
+
+        // e.g. "GET /callback?code=abc&state=xyz HTTP/1.1"
+        let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+        let url = Url::parse(&format!("http://127.0.0.1{}", target))?;
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+        if url.path() != "/callback" {
+            stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                .await?;
+            continue;
+        }
+
+        let page = "<html><body>Signed in, you can close this window.</body></html>";
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\n\r\n{}",
+            page.len(),
+            page
+        );
+        stream.write_all(response.as_bytes()).await?;
+        return Ok(params);
+    }
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpStream;
+
+    type Forms = Arc<Mutex<Vec<HashMap<String, String>>>>;
+
+    /// A stand-in token endpoint that records every form it receives.
+    /// Refreshing with "expired" fails, every other grant returns a numbered token.
+    async fn token_server() -> (String, Forms) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let forms: Forms = Arc::default();
+        let received = forms.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                let body = loop {
+                    let read = socket.read(&mut buffer).await.unwrap_or_default();
+                    if read == 0 {
+                        break None;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request).to_string();
+                    let Some((head, body)) = text.split_once("\r\n\r\n") else {
+                        continue;
+                    };
+                    let length = head
+                        .lines()
+                        .filter_map(|line| line.split_once(':'))
+                        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+                        .unwrap_or_default();
+                    if body.len() >= length {
+                        break Some(body.to_string());
+                    }
+                };
+                let Some(body) = body else { continue };
+
+                let form: HashMap<String, String> = Url::parse(&format!("http://form/?{}", body))
+                    .unwrap()
+                    .query_pairs()
+                    .into_owned()
+                    .collect();
+                let (status, reply) = {
+                    let mut forms = received.lock().unwrap();
+                    forms.push(form.clone());
+                    let count = forms.len();
+                    if form.get("refresh_token").map(String::as_str) == Some("expired") {
+                        ("400 Bad Request", json!({"error": "invalid_grant"}))
+                    } else {
+                        let token = json!({
+                            "access_token": format!("token-{}", count),
+                            "expires_in": 3600,
+                            "refresh_token": format!("refresh-{}", count)
+                        });
+                        ("200 OK", token)
+                    }
+                };
+                let reply = reply.to_string();
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    status,
+                    reply.len(),
+                    reply
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            }
+        });
+        (format!("http://{}", address), forms)
+    }
+
+    /// A config whose token_url goes through the `AUTH_HOST` variable
+    fn setup(name: &str, base_url: &str, grant_type: &str) -> (OAuth2Config, VariableStore) {
+        let dir =
+            std::env::temp_dir().join(format!("rest_api_oauth2_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config: OAuth2Config = serde_json::from_value(json!({
+            "grant_type": grant_type,
+            "token_url": "{{AUTH_HOST}}/token",
+            "authorize_url": "{{AUTH_HOST}}/authorize",
+            "client_id": "{{CLIENT_ID}}",
+            "scope": "read"
+        }))
+        .unwrap();
+        let variables = VariableStore::new(&dir.to_string_lossy()).with_overrides(&[
+            ("AUTH_HOST".to_string(), base_url.to_string()),
+            ("CLIENT_ID".to_string(), "app".to_string()),
+        ]);
+        let config = config.resolve(&variables).unwrap();
+        (config, variables)
+    }
+
+    fn grant_types(forms: &Forms) -> Vec<String> {
+        forms
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|form| form["grant_type"].clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn a_cached_token_is_used_until_it_expires_then_refreshed() {
+        let (base_url, forms) = token_server().await;
+        let (config, variables) = setup("cache", &base_url, "client_credentials");
+        assert_eq!(config.token_url, format!("{}/token", base_url));
+        let client = Client::new();
+
+        assert_eq!(
+            config.access_token(&client, &variables).await.unwrap(),
+            "token-1"
+        );
+        assert_eq!(
+            config.access_token(&client, &variables).await.unwrap(),
+            "token-1"
+        );
+        assert_eq!(grant_types(&forms), ["client_credentials"]);
+        assert_eq!(forms.lock().unwrap()[0]["client_id"], "app");
+        assert_eq!(forms.lock().unwrap()[0]["scope"], "read");
+
+        // Inside the expiry margin counts as expired
+        let expires_at = (unix_now() + EXPIRY_MARGIN_SECS - 1).to_string();
+        variables
+            .set("OAUTH2_ACCESS_TOKEN_EXPIRES_AT", &expires_at)
+            .unwrap();
+        assert_eq!(
+            config.access_token(&client, &variables).await.unwrap(),
+            "token-2"
+        );
+        assert_eq!(grant_types(&forms), ["client_credentials", "refresh_token"]);
+        assert_eq!(forms.lock().unwrap()[1]["refresh_token"], "refresh-1");
+        assert_eq!(variables.get("OAUTH2_ACCESS_TOKEN").unwrap(), "token-2");
+        assert_eq!(
+            variables.get("OAUTH2_ACCESS_TOKEN_REFRESH_TOKEN").unwrap(),
+            "refresh-2"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_failed_refresh_falls_back_to_the_grant() {
+        let (base_url, forms) = token_server().await;
+        let (config, variables) = setup("refresh", &base_url, "client_credentials");
+        variables.set("OAUTH2_ACCESS_TOKEN", "stale").unwrap();
+        variables
+            .set("OAUTH2_ACCESS_TOKEN_EXPIRES_AT", "1")
+            .unwrap();
+        variables
+            .set("OAUTH2_ACCESS_TOKEN_REFRESH_TOKEN", "expired")
+            .unwrap();
+
+        let token = config
+            .access_token(&Client::new(), &variables)
+            .await
+            .unwrap();
+        assert_eq!(token, "token-2");
+        assert_eq!(grant_types(&forms), ["refresh_token", "client_credentials"]);
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_share_one_token_request() {
+        let (base_url, forms) = token_server().await;
+        let (config, variables) = setup("concurrent", &base_url, "client_credentials");
+        let client = Client::new();
+
+        let tokens = futures_util::future::join_all(
+            (0..4).map(|_| config.access_token(&client, &variables)),
+        )
+        .await;
+        for token in tokens {
+            assert_eq!(token.unwrap(), "token-1");
+        }
+        assert_eq!(grant_types(&forms), ["client_credentials"]);
+    }
+
+    /// Play the browser: follow the redirect back to the listener
+    async fn redirect(port: u16, query: &str) {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let request = format!(
+            "GET /callback?{} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            query
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).await;
+    }
+
+    #[tokio::test]
+    async fn authorization_code_sends_the_pkce_verifier_and_checks_the_state() {
+        let (base_url, forms) = token_server().await;
+        let (config, _) = setup("pkce", &base_url, "authorization_code");
+        let client = Client::new();
+
+        let url = config.authorize_request("verifier", "state-1").unwrap();
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        assert!(url
+            .as_str()
+            .starts_with(&format!("{}/authorize?", base_url)));
+        assert_eq!(params["response_type"], "code");
+        assert_eq!(params["client_id"], "app");
+        assert_eq!(params["redirect_uri"], "http://127.0.0.1:8765/callback");
+        assert_eq!(params["state"], "state-1");
+        assert_eq!(params["code_challenge_method"], "S256");
+        assert_eq!(
+            params["code_challenge"],
+            URL_SAFE_NO_PAD.encode(Sha256::digest(b"verifier"))
+        );
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(redirect(port, "code=c1&state=forged"));
+        let error = config
+            .exchange_code(&client, &listener, "verifier", "state-1")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("state does not match"));
+        assert!(forms.lock().unwrap().is_empty());
+
+        tokio::spawn(redirect(port, "code=c1&state=state-1"));
+        let token = config
+            .exchange_code(&client, &listener, "verifier", "state-1")
+            .await
+            .unwrap();
+        assert_eq!(token.access_token, "token-1");
+        let forms = forms.lock().unwrap();
+        assert_eq!(forms[0]["grant_type"], "authorization_code");
+        assert_eq!(forms[0]["code"], "c1");
+        assert_eq!(forms[0]["code_verifier"], "verifier");
+        assert_eq!(forms[0]["redirect_uri"], "http://127.0.0.1:8765/callback");
+    }
+}
@@ -0,0 +1,192 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde_json::{Map, Value};
+
+use crate::variables::VariableStore;
+
+/// Everything except the unreserved characters is encoded inside a path segment
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// A piece of one path segment of an endpoint
+#[derive(Debug, PartialEq)]
+pub(crate) enum SegmentPart<'a> {
+    Text(&'a str),
+    /// `:name` as a whole segment, or `{name}` anywhere inside one
+    Param(&'a str),
+    /// `{{VAR_NAME}}` with its braces, resolved later as a variable
+    Placeholder(&'a str),
+}
+
+/// Split a path segment such as `:id`, `v{version}` or `{{TENANT}}` into its parts
+pub(crate) fn segment_parts(segment: &str) -> Vec<SegmentPart<'_>> {
+    if let Some(name) = segment.strip_prefix(':').filter(|name| !name.is_empty()) {
+        return vec![SegmentPart::Param(name)];
+    }
+
+    let mut parts = Vec::new();
+    let mut rest = segment;
+    while let Some(start) = rest.find('{') {
+        let (text, braces) = rest.split_at(start);
+        if braces.starts_with("{{") {
+            let end = braces.find("}}").map_or(braces.len(), |end| end + 2);
+            parts.push(SegmentPart::Text(text));
+            parts.push(SegmentPart::Placeholder(&braces[..end]));
+            rest = &braces[end..];
+            continue;
+        }
+        let Some(end) = braces.find('}') else {
+            break;
+        };
+        parts.push(SegmentPart::Text(text));
+        parts.push(SegmentPart::Param(&braces[1..end]));
+        rest = &braces[end + 1..];
+    }
+    parts.push(SegmentPart::Text(rest));
+    parts.retain(|part| *part != SegmentPart::Text(""));
+    parts
+}
+
+/// Fill `/users/:id` and `/users/{id}` segments of an endpoint.
+/// A value comes from `--path id=VALUE`, then `req_path`, then the `id` variable
+/// (where captured response values are saved), and is percent-encoded.
+pub fn fill_path_params(
+    end_point: &str,
+    req_path: &Option<Map<String, Value>>,
+    overrides: &[(String, String)],
+    variables: &VariableStore,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (path, query) = match end_point.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (end_point, None),
+    };
+
+    let lookup = |name: &str| -> Result<String, Box<dyn std::error::Error>> {
+        let value = if let Some((_, value)) = overrides.iter().rev().find(|(key, _)| key == name) {
+            value.clone()
+        } else if let Some(value) = req_path.as_ref().and_then(|map| map.get(name)) {
+            match value {
+                Value::String(text) => text.clone(),
+                other => other.to_string(),
+            }
+        } else if let Ok(value) = variables.get(name) {
+            value
+        } else {
+            return Err(format!(
+                "Missing path parameter '{}' (set it with --path {}=VALUE)",
+                name, name
+            )
+            .into());
+        };
+
+        let value = variables.resolve_str(&value)?;
+        Ok(utf8_percent_encode(&value, PATH_SEGMENT).to_string())
+    };
+
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        let mut filled = String::new();
+        for part in segment_parts(segment) {
+            match part {
+                SegmentPart::Text(text) | SegmentPart::Placeholder(text) => filled.push_str(text),
+                SegmentPart::Param(name) => filled.push_str(&lookup(name)?),
+            }
+        }
+        segments.push(filled);
+    }
+
+    let path = segments.join("/");
+    Ok(match query {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn variables() -> VariableStore {
+        VariableStore::new("/nonexistent/rest_api_path")
+            .with_overrides(&[("user_id".to_string(), "42".to_string())])
+    }
+
+    fn fill(end_point: &str, req_path: Value, overrides: &[(&str, &str)]) -> String {
+        let overrides: Vec<(String, String)> = overrides
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        fill_path_params(
+            end_point,
+            &req_path.as_object().cloned(),
+            &overrides,
+            &variables(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn colon_and_brace_params_are_filled() {
+        let req_path = json!({"id": 7, "version": "2"});
+        assert_eq!(fill("/users/:id", req_path.clone(), &[]), "/users/7");
+        assert_eq!(fill("/users/{id}", req_path.clone(), &[]), "/users/7");
+        assert_eq!(
+            fill("/v{version}/users/{id}.json?x=:id", req_path, &[]),
+            "/v2/users/7.json?x=:id"
+        );
+    }
+
+    #[test]
+    fn cli_values_win_over_req_path_and_variables() {
+        assert_eq!(
+            fill("/users/:id", json!({"id": 7}), &[("id", "1"), ("id", "9")]),
+            "/users/9"
+        );
+        assert_eq!(fill("/users/:user_id", json!({}), &[]), "/users/42");
+        assert_eq!(
+            fill("/users/:id", json!({"id": "{{user_id}}"}), &[]),
+            "/users/42"
+        );
+    }
+
+    #[test]
+    fn values_are_encoded_as_one_segment() {
+        assert_eq!(
+            fill("/files/:name", json!({"name": "a b/c&d"}), &[]),
+            "/files/a%20b%2Fc%26d"
+        );
+    }
+
+    #[test]
+    fn placeholders_and_bare_colons_are_left_alone() {
+        assert_eq!(
+            fill("/{{TENANT}}/items/:/x", json!({}), &[]),
+            "/{{TENANT}}/items/:/x"
+        );
+    }
+
+    #[test]
+    fn segments_split_into_text_params_and_placeholders() {
+        use SegmentPart::*;
+        assert_eq!(segment_parts("users"), [Text("users")]);
+        assert_eq!(segment_parts(":id"), [Param("id")]);
+        assert_eq!(segment_parts(":"), [Text(":")]);
+        assert_eq!(segment_parts("v{version}"), [Text("v"), Param("version")]);
+        assert_eq!(
+            segment_parts("{id}.{format}"),
+            [Param("id"), Text("."), Param("format")]
+        );
+        assert_eq!(segment_parts("{{TENANT}}"), [Placeholder("{{TENANT}}")]);
+        assert_eq!(segment_parts("a{b"), [Text("a{b")]);
+    }
+
+    #[test]
+    fn a_missing_parameter_is_an_error_naming_it() {
+        let error = fill_path_params("/orders/:order_id", &None, &[], &variables()).unwrap_err();
+        assert!(error.to_string().contains("'order_id'"));
+        assert!(error.to_string().contains("--path order_id=VALUE"));
+    }
+}
@@ -0,0 +1,129 @@
+use reqwest::Url;
+use serde_json::{Map, Value};
+
+use crate::variables::VariableStore;
+
+/// Turn `req_query` into ordered key/value pairs.
+/// Arrays repeat the key, `null` drops it, and "{{VAR_NAME}}" placeholders are resolved.
+/// Keys given in `overrides` (from `--query key=value`) replace the configured values.
+pub fn query_pairs(
+    req_query: &Option<Map<String, Value>>,
+    overrides: &[(String, String)],
+    variables: &VariableStore,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let mut pairs = Vec::new();
+
+    if let Some(req_query) = req_query {
+        for (key, value) in req_query {
+            if overrides
+                .iter()
+                .any(|(override_key, _)| override_key == key)
+            {
+                continue;
+            }
+
+            let values = match value {
+                Value::Array(items) => items.iter().collect(),
+                _ => vec![value],
+            };
+            for value in values {
+                let text = match value {
+                    Value::Null => continue,
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                pairs.push((key.clone(), variables.resolve_str(&text)?));
+            }
+        }
+    }
+
+    for (key, value) in overrides {
+        pairs.push((key.clone(), variables.resolve_str(value)?));
+    }
+
+    Ok(pairs)
+}
+
+/// Append URL-encoded pairs to the query string of `url`
+pub fn append_query(
+    url: &str,
+    pairs: &[(String, String)],
+) -> Result<String, Box<dyn std::error::Error>> {
+    if pairs.is_empty() {
+        return Ok(url.to_string());
+    }
+
+    let mut url = Url::parse(url)?;
+    url.query_pairs_mut().extend_pairs(pairs);
+    Ok(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn req_query(value: Value) -> Option<Map<String, Value>> {
+        value.as_object().cloned()
+    }
+
+    fn variables() -> VariableStore {
+        VariableStore::new("/nonexistent/rest_api_query")
+            .with_overrides(&[("TEAM".to_string(), "core".to_string())])
+    }
+
+    fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn arrays_repeat_the_key_and_null_drops_it() {
+        let query = req_query(json!({
+            "tag": ["a", "b", 3],
+            "page": 2,
+            "draft": null,
+            "team": "{{TEAM}}"
+        }));
+        let pairs_found = query_pairs(&query, &[], &variables()).unwrap();
+        assert_eq!(
+            pairs_found,
+            pairs(&[
+                ("page", "2"),
+                ("tag", "a"),
+                ("tag", "b"),
+                ("tag", "3"),
+                ("team", "core")
+            ])
+        );
+    }
+
+    #[test]
+    fn cli_query_overrides_replace_the_collection_values() {
+        let query = req_query(json!({"tag": ["a", "b"], "page": 1}));
+        let overrides = pairs(&[("tag", "c"), ("limit", "{{TEAM}}")]);
+        let pairs_found = query_pairs(&query, &overrides, &variables()).unwrap();
+        assert_eq!(
+            pairs_found,
+            pairs(&[("page", "1"), ("tag", "c"), ("limit", "core")])
+        );
+    }
+
+    #[test]
+    fn values_are_url_encoded_after_the_existing_query() {
+        let url = append_query(
+            "http://localhost/search?raw=1",
+            &pairs(&[("q", "a b&c/d"), ("q", "é=?")]),
+        )
+        .unwrap();
+        assert_eq!(
+            url,
+            "http://localhost/search?raw=1&q=a+b%26c%2Fd&q=%C3%A9%3D%3F"
+        );
+        assert_eq!(
+            append_query("http://localhost/search", &[]).unwrap(),
+            "http://localhost/search"
+        );
+    }
+}
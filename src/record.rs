@@ -0,0 +1,486 @@
+use colored::Colorize;
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE},
+    redirect::Policy,
+    Method, StatusCode, Url,
+};
+use serde_json::{json, Map, Value};
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::file_ops::{expand_tilde, read_from_file, safe_file_name, write_atomic};
+use crate::mock::{
+    log_request, query_pairs, read_request, write_response, IncomingRequest, MockResponse,
+};
+
+/// The recorded collection inside the output directory
+pub const COLLECTION_FILE: &str = "collection.json";
+
+/// Headers that belong to one connection and are not passed through the proxy
+const HOP_BY_HOP: [&str; 9] = [
+    "host",
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "te",
+    "upgrade",
+    "content-length",
+    // Bodies are recorded as they are, so ask the backend not to compress them
+    "accept-encoding",
+];
+
+/// A proxy in front of `target` that records every request into a collection.
+///
+/// `out_dir/collection.json` gets one `RequestData` per method, path and query,
+/// with its body in `out_dir/bodies/` and its last response in `out_dir/fixtures/`,
+/// ready for `replay`. Recording again into the same directory adds to it.
+pub struct Recorder {
+    target: String,
+    out_dir: PathBuf,
+    client: reqwest::Client,
+    collection: Mutex<Value>,
+}
+
+impl Recorder {
+    pub fn new(target: &str, out_dir: &str) -> Result<Recorder, Box<dyn std::error::Error>> {
+        let target = target.trim_end_matches('/').to_string();
+        Url::parse(&target).map_err(|e| format!("Invalid target URL '{}': {}", target, e))?;
+
+        let mut out_dir = PathBuf::from(expand_tilde(out_dir));
+        if out_dir.is_relative() {
+            out_dir = env::current_dir()?.join(out_dir);
+        }
+
+        let collection_path = out_dir.join(COLLECTION_FILE);
+        let collection = if collection_path.exists() {
+            serde_json::from_str(&read_from_file(&collection_path.to_string_lossy())?)?
+        } else {
+            json!({ "base_url": target, "requests": [] })
+        };
+
+        Ok(Recorder {
+            target,
+            out_dir,
+            client: reqwest::Client::builder()
+                .redirect(Policy::none())
+                .build()?,
+            collection: Mutex::new(collection),
+        })
+    }
+
+    /// Forward and record requests until the process is stopped
+    pub async fn serve(self, host: &str, port: u16) -> io::Result<()> {
+        self.serve_on(TcpListener::bind((host, port)).await?).await
+    }
+
+    /// `serve` on a listener that is already bound
+    pub async fn serve_on(self, listener: TcpListener) -> io::Result<()> {
+        println!(
+            "{} http://{} -> {}",
+            "RECORD   :".blue().bold(),
+            listener.local_addr()?.to_string().green(),
+            self.target.yellow()
+        );
+        println!(
+            "{} {}",
+            "COLLECTION:".blue().bold(),
+            self.out_dir.join(COLLECTION_FILE).display()
+        );
+        println!();
+
+        let recorder = Arc::new(self);
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let recorder = Arc::clone(&recorder);
+            tokio::spawn(async move {
+                if let Err(err) = recorder.handle(stream).await {
+                    eprintln!("{}", format!("Record connection failed: {}", err).red());
+                }
+            });
+        }
+    }
+
+    /// Forward one request, record it and pass the response back
+    async fn handle(self: Arc<Self>, stream: TcpStream) -> io::Result<()> {
+        let mut stream = BufReader::new(stream);
+        let Some(request) = read_request(&mut stream).await? else {
+            return Ok(());
+        };
+
+        let forwarded = self.forward(&request).await.map_err(|e| e.to_string());
+        let (request, response, tag) = match forwarded {
+            Ok((response, headers)) => {
+                // Writing the files blocks, and the collection lock is held meanwhile
+                let recorder = Arc::clone(&self);
+                let (request, response, recorded) = tokio::task::spawn_blocking(move || {
+                    let recorded = recorder
+                        .record(&request, &response, &headers)
+                        .map_err(|e| e.to_string());
+                    (request, response, recorded)
+                })
+                .await
+                .map_err(io::Error::other)?;
+                match recorded {
+                    Ok(tag) => (request, response, Some(tag)),
+                    Err(err) => {
+                        eprintln!("{}", format!("Not recorded: {}", err).red());
+                        (request, response, None)
+                    }
+                }
+            }
+            Err(err) => {
+                let body = json!({ "error": format!("Backend not reached: {}", err) });
+                let response = MockResponse {
+                    status: StatusCode::BAD_GATEWAY,
+                    headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+                    body: body.to_string().into_bytes(),
+                };
+                (request, response, None)
+            }
+        };
+
+        log_request("RECORD   :", &request, response.status, tag.as_deref());
+        write_response(stream.get_mut(), &response).await
+    }
+
+    /// Send the request to the target, the response headers are also returned as received
+    async fn forward(
+        &self,
+        request: &IncomingRequest,
+    ) -> Result<(MockResponse, HeaderMap), Box<dyn std::error::Error>> {
+        let method = Method::from_bytes(request.method.as_bytes())?;
+        let mut builder = self
+            .client
+            .request(method, format!("{}{}", self.target, request.target));
+        for (name, value) in &request.headers {
+            if !HOP_BY_HOP.contains(&name.as_str()) {
+                builder = builder.header(name, value);
+            }
+        }
+        if !request.body.is_empty() {
+            builder = builder.body(request.body.clone());
+        }
+
+        let response = builder.send().await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?.to_vec();
+
+        let response_headers = headers
+            .iter()
+            .filter(|(name, _)| !HOP_BY_HOP.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        Ok((
+            MockResponse {
+                status,
+                headers: response_headers,
+                body,
+            },
+            headers,
+        ))
+    }
+
+    /// Add or update the `RequestData` of a request, with its body file and response fixture
+    fn record(
+        &self,
+        request: &IncomingRequest,
+        response: &MockResponse,
+        headers: &HeaderMap,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let url = Url::parse(&format!("http://record{}", request.target))?;
+        let end_point = url.path().to_string();
+        let mut req_query = Map::new();
+        for (name, value) in url.query_pairs().into_owned() {
+            match req_query.get_mut(&name) {
+                Some(Value::Array(values)) => values.push(Value::String(value)),
+                Some(existing) => *existing = json!([existing.clone(), value]),
+                None => {
+                    req_query.insert(name, Value::String(value));
+                }
+            }
+        }
+        let query = query_pairs(&Some(req_query.clone()));
+
+        let mut collection = self.collection.lock().unwrap_or_else(|e| e.into_inner());
+        let requests = collection["requests"]
+            .as_array_mut()
+            .ok_or("The recorded collection has no \"requests\" array")?;
+
+        // The same method, path and query replaces the earlier recording
+        let existing = requests.iter().position(|item| {
+            item["req_type"] == request.method.as_str()
+                && item["req_end_point"] == end_point.as_str()
+                && query_pairs(&item["req_query"].as_object().cloned()) == query
+        });
+        let tag = match existing {
+            Some(index) => requests[index]["req_tag"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            None => unique_tag(requests, &request.method, &end_point),
+        };
+
+        let mut item = json!({
+            "req_tag": tag,
+            "req_title": format!("{} {}", request.method, end_point),
+            "req_type": request.method,
+            "req_end_point": end_point,
+        });
+        if !req_query.is_empty() {
+            item["req_query"] = Value::Object(req_query);
+        }
+        if let Some(req_body) = self.record_body(request, &tag)? {
+            item["req_body"] = req_body;
+        }
+
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let fixture = Path::new("fixtures").join(format!(
+            "{}.{}",
+            safe_file_name(&tag),
+            fixture_extension(content_type)
+        ));
+        write_atomic(&self.out_dir.join(&fixture), &response.body)?;
+
+        // A recorded Date would be stale on replay, a header sent several times becomes a list
+        let mut mock_headers = Map::new();
+        for (name, value) in response.headers.iter().filter(|(name, _)| name != "date") {
+            let value = Value::String(value.clone());
+            match mock_headers.get_mut(name) {
+                Some(Value::Array(values)) => values.push(value),
+                Some(existing) => *existing = json!([existing.clone(), value]),
+                None => {
+                    mock_headers.insert(name.clone(), value);
+                }
+            }
+        }
+        item["mock"] = json!({
+            "status": response.status.as_u16(),
+            "example_file": fixture,
+            "headers": mock_headers,
+        });
+
+        match existing {
+            Some(index) => requests[index] = item,
+            None => requests.push(item),
+        }
+        let contents = serde_json::to_string_pretty(&*collection)?;
+        write_atomic(&self.out_dir.join(COLLECTION_FILE), contents.as_bytes())?;
+        Ok(tag)
+    }
+
+    /// Save a JSON or form body as `bodies/TAG.json`, other bodies cannot be replayed
+    fn record_body(
+        &self,
+        request: &IncomingRequest,
+        tag: &str,
+    ) -> Result<Option<Value>, Box<dyn std::error::Error>> {
+        if request.body.is_empty() {
+            return Ok(None);
+        }
+
+        let is_form = request
+            .headers
+            .get("content-type")
+            .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+        let (body_type, body) = if let Ok(body) = serde_json::from_slice::<Value>(&request.body) {
+            ("RAW", body)
+        } else if is_form {
+            let form = String::from_utf8_lossy(&request.body);
+            let fields: Map<String, Value> = Url::parse(&format!("http://record/?{}", form))?
+                .query_pairs()
+                .map(|(name, value)| (name.into_owned(), Value::String(value.into_owned())))
+                .collect();
+            ("FORM_DATA", Value::Object(fields))
+        } else {
+            eprintln!(
+                "{}",
+                format!(
+                    "Body of {} not recorded: it is neither JSON nor a form",
+                    tag
+                )
+                .yellow()
+            );
+            return Ok(None);
+        };
+
+        // Relative to out_dir, so the recording can be moved or committed
+        let body_file = Path::new("bodies").join(format!("{}.json", safe_file_name(tag)));
+        write_atomic(
+            &self.out_dir.join(&body_file),
+            serde_json::to_string_pretty(&body)?.as_bytes(),
+        )?;
+        Ok(Some(json!({
+            "body_type": body_type,
+            "body_file": body_file,
+        })))
+    }
+}
+
+/// e.g. `get_users_42`, with a number added when the tag is taken
+fn unique_tag(requests: &[Value], method: &str, end_point: &str) -> String {
+    let slug: String = end_point
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let slug = slug
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    let base = if slug.is_empty() {
+        format!("{}_root", method.to_lowercase())
+    } else {
+        format!("{}_{}", method.to_lowercase(), slug)
+    };
+
+    let taken = |tag: &str| requests.iter().any(|item| item["req_tag"] == tag);
+    let mut tag = base.clone();
+    let mut count = 1;
+    while taken(&tag) {
+        count += 1;
+        tag = format!("{}_{}", base, count);
+    }
+    tag
+}
+
+fn fixture_extension(content_type: &str) -> &'static str {
+    if content_type.contains("json") {
+        "json"
+    } else if content_type.contains("xml") {
+        "xml"
+    } else if content_type.contains("html") {
+        "html"
+    } else if content_type.starts_with("text/") {
+        "txt"
+    } else {
+        "body"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection::AppMainRequest;
+    use crate::mock::MockServer;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// A stand-in backend answering 201 with two cookies and the request body it received
+    async fn backend() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut stream = BufReader::new(stream);
+                let Ok(Some(request)) = read_request(&mut stream).await else {
+                    continue;
+                };
+                let body = json!({
+                    "id": 1,
+                    "target": request.target,
+                    "received": String::from_utf8_lossy(&request.body),
+                })
+                .to_string();
+                let response = format!(
+                    "HTTP/1.1 201 Created\r\nContent-Type: application/json\r\n\
+                     Set-Cookie: session=abc; Path=/\r\nSet-Cookie: theme=dark\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = stream.get_mut().write_all(response.as_bytes()).await;
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    /// Serve on a free port and return its address
+    async fn spawn_server<F>(serve: impl FnOnce(TcpListener) -> F) -> String
+    where
+        F: std::future::Future<Output = io::Result<()>> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener));
+        address.to_string()
+    }
+
+    /// Send a raw HTTP request and return the response head and body
+    async fn send(address: &str, request: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.to_lowercase(), body.to_string())
+    }
+
+    const CHUNKED_REQUEST: &str = "POST /users?team=a HTTP/1.1\r\nHost: localhost\r\n\
+        Content-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n\
+        8\r\n{\"name\":\r\n7;ext=1\r\n \"Ann\"}\r\n0\r\n\r\n";
+
+    #[tokio::test]
+    async fn a_recorded_request_replays_with_the_same_response() {
+        let target = backend().await;
+        let out_dir = env::temp_dir().join(format!("rest_api_record_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&out_dir);
+        let recorder = Recorder::new(&target, &out_dir.to_string_lossy()).unwrap();
+        let proxy = spawn_server(|listener| recorder.serve_on(listener)).await;
+
+        // The chunked body reaches the backend and is recorded
+        let (head, body) = send(&proxy, CHUNKED_REQUEST).await;
+        assert!(head.starts_with("http/1.1 201"));
+        assert!(head.contains("set-cookie: session=abc; path=/"));
+        assert!(head.contains("set-cookie: theme=dark"));
+        let recorded: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(recorded["received"], r#"{"name": "Ann"}"#);
+
+        let collection_file = out_dir.join(COLLECTION_FILE);
+        let collection: Value =
+            serde_json::from_str(&std::fs::read_to_string(&collection_file).unwrap()).unwrap();
+        let item = &collection["requests"][0];
+        assert_eq!(item["req_tag"], "post_users");
+        assert_eq!(item["req_query"], json!({"team": "a"}));
+        assert_eq!(item["req_body"]["body_file"], "bodies/post_users.json");
+        assert_eq!(item["mock"]["example_file"], "fixtures/post_users.json");
+        assert_eq!(
+            item["mock"]["headers"]["set-cookie"],
+            json!(["session=abc; Path=/", "theme=dark"])
+        );
+        let saved_body = std::fs::read_to_string(out_dir.join("bodies/post_users.json")).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&saved_body).unwrap(),
+            json!({"name": "Ann"})
+        );
+
+        // Replay answers from the recording alone
+        let collection = AppMainRequest::load(&collection_file.to_string_lossy()).unwrap();
+        let replay = MockServer::replay(&collection);
+        let replay = spawn_server(|listener| replay.serve_on(listener)).await;
+
+        let (replay_head, replay_body) = send(&replay, CHUNKED_REQUEST).await;
+        assert!(replay_head.starts_with("http/1.1 201"));
+        assert!(replay_head.contains("set-cookie: session=abc; path=/"));
+        assert!(replay_head.contains("set-cookie: theme=dark"));
+        assert_eq!(replay_body, body);
+
+        let other_query =
+            "POST /users?team=b HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n";
+        let (head, _) = send(&replay, other_query).await;
+        assert!(head.starts_with("http/1.1 404"));
+    }
+}
@@ -0,0 +1,519 @@
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE},
+    multipart, Method, RequestBuilder, StatusCode,
+};
+use reqwest_cookie_store::CookieStoreMutex;
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::auth::{digest_authorization, ApiKeyLocation, AuthConfig};
+use crate::capture::CapturedResponse;
+use crate::collection::{AppMainRequest, RequestData, RequestDataBody};
+use crate::file_ops::{expand_tilde, read_from_file, write_to_file};
+use crate::history::HistoryRequest;
+use crate::path_params::fill_path_params;
+use crate::query::{append_query, query_pairs};
+use crate::schema::SchemaViolation;
+use crate::variables::VariableStore;
+
+/// `--query` and `--path` values, they win over `req_query` and `req_path`
+#[derive(Clone, Debug, Default)]
+pub struct RequestOverrides {
+    pub query: Vec<(String, String)>,
+    pub path: Vec<(String, String)>,
+}
+
+/// A request with its URL and body placeholders filled in, before auth is applied
+#[derive(Clone, Debug)]
+pub struct ResolvedRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Option<Value>,
+}
+
+impl AppMainRequest {
+    /// Fill in the URL, query and body of a request without sending it
+    pub fn resolve(
+        &self,
+        request: &RequestData,
+        variables: &VariableStore,
+        overrides: &RequestOverrides,
+    ) -> Result<ResolvedRequest, Box<dyn std::error::Error>> {
+        let body = match &request.req_body {
+            Some(req_body) => {
+                let mut body_data = request_body_data(req_body)?;
+                resolve_placeholders(&mut body_data, variables)?;
+                Some(body_data)
+            }
+            None => None,
+        };
+
+        Ok(ResolvedRequest {
+            method: request.req_type.clone(),
+            url: build_url(self, request, variables, overrides)?,
+            headers: self.create_header_map(),
+            body,
+        })
+    }
+
+    /// Send a request with its auth, retry and client settings and read the whole response
+    pub async fn execute(
+        &self,
+        request: &RequestData,
+        variables: &VariableStore,
+        cookie_jar: &Option<Arc<CookieStoreMutex>>,
+        overrides: &RequestOverrides,
+    ) -> Result<CapturedResponse, Box<dyn std::error::Error>> {
+        let client = build_client(self, request, cookie_jar)?;
+        let url = build_url(self, request, variables, overrides)?;
+        let headers = self.create_header_map();
+        let auth = select_auth(self, request, variables)
+            .resolve(&client, variables)
+            .await?;
+
+        let send = || {
+            make_http_request(
+                &client,
+                &request.req_type,
+                url.clone(),
+                headers.clone(),
+                &auth,
+                variables,
+                request.req_body.clone(),
+            )
+        };
+        let response = match &request.retry {
+            Some(retry) => retry.run(send).await?,
+            None => send().await?,
+        };
+        Ok(CapturedResponse::read(response).await?)
+    }
+
+    /// Save the `req_variable_response_value` captures of a 2xx response.
+    /// They last for the run, `req_variable_is_save` also writes them to disk.
+    pub fn capture(
+        &self,
+        request: &RequestData,
+        response: &CapturedResponse,
+        variables: &VariableStore,
+    ) -> io::Result<()> {
+        let variable_response_value = request
+            .req_variable_response_value
+            .clone()
+            .unwrap_or_default();
+        if !response.status.is_success() || variable_response_value.is_null() {
+            return Ok(());
+        }
+
+        write_to_file(
+            response,
+            &variable_response_value,
+            &self.variable_access_token_file,
+            request.req_variable_is_save.unwrap_or_default(),
+            variables,
+        )
+    }
+
+    /// Validate a response body against the `schema` of its request, None without one
+    pub fn validate(
+        &self,
+        request: &RequestData,
+        response: &CapturedResponse,
+    ) -> Result<Option<Vec<SchemaViolation>>, Box<dyn std::error::Error>> {
+        match &request.schema {
+            Some(schema) => Ok(Some(
+                schema.validate(self.openapi.as_deref(), &response.json_or_text())?,
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The HTTP client for a request, collection settings merged with the request ones
+pub(crate) fn build_client(
+    app_main_request: &AppMainRequest,
+    request: &RequestData,
+    cookie_jar: &Option<Arc<CookieStoreMutex>>,
+) -> Result<reqwest::Client, Box<dyn std::error::Error>> {
+    let client_config = app_main_request
+        .client
+        .clone()
+        .unwrap_or_default()
+        .merge(&request.client.clone().unwrap_or_default());
+    let mut client_builder = client_config.builder()?;
+    if let Some(jar) = cookie_jar {
+        client_builder = client_builder.cookie_provider(Arc::clone(jar));
+    }
+    Ok(client_builder.build()?)
+}
+
+/// The full URL: base_url, the endpoint with its path parameters, req_params and req_query
+pub(crate) fn build_url(
+    app_main_request: &AppMainRequest,
+    request: &RequestData,
+    variables: &VariableStore,
+    overrides: &RequestOverrides,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let params = request.req_params.clone().unwrap_or_default();
+
+    // let main_url = app_main_request.base_url.clone() + &request.req_end_point;
+    let end_point = fill_path_params(
+        &request.req_end_point,
+        &request.req_path,
+        &overrides.path,
+        variables,
+    )?;
+    let main_url = format!(
+        "{}{}{}",
+        app_main_request.base_url.clone(),
+        end_point,
+        params
+    );
+    let pairs = query_pairs(&request.req_query, &overrides.query, variables)?;
+    append_query(&main_url, &pairs)
+}
+
+/// Request auth wins, then the legacy `req_variable_type`, then the collection auth
+pub(crate) fn select_auth(
+    app_main_request: &AppMainRequest,
+    request: &RequestData,
+    variables: &VariableStore,
+) -> AuthConfig {
+    let main_variable_dir = app_main_request.variable_dir.clone().unwrap_or_default();
+    let main_variable_access_token_file = app_main_request
+        .variable_access_token_file
+        .clone()
+        .unwrap_or_default();
+    let variable_type = &request.req_variable_type;
+
+    match (&request.auth, &app_main_request.auth) {
+        (Some(auth), _) => auth.clone(),
+        (None, Some(auth)) if variable_type.is_none() => auth.clone(),
+        _ => AuthConfig::legacy(
+            variable_type.clone(),
+            match main_variable_access_token_file.strip_suffix(".txt") {
+                Some(name) => variables.get(name).unwrap_or_default(),
+                None => read_from_file(
+                    format!("{}/{}", &main_variable_dir, main_variable_access_token_file).as_str(),
+                )
+                .unwrap_or_default(),
+            },
+        ),
+    }
+}
+
+/// The JSON content of `body_file`, placeholders not yet filled in
+pub fn request_body_data(req_body: &RequestDataBody) -> Result<Value, Box<dyn std::error::Error>> {
+    let body_file_path = expand_tilde(&req_body.body_file);
+    let body_file = File::open(&body_file_path)
+        .map_err(|e| format!("Failed to read body file {}: {}", body_file_path, e))?;
+    let body_reader = BufReader::new(body_file);
+    let body_data: Value = serde_json::from_reader(body_reader)
+        .map_err(|e| format!("Body file {} is not proper JSON: {}", body_file_path, e))?;
+    Ok(body_data)
+}
+
+fn handle_get_request(
+    client: &reqwest::Client,
+    url: String,
+    headers: HeaderMap,
+) -> Result<RequestBuilder, Box<dyn std::error::Error>> {
+    Ok(client.get(url).headers(headers))
+}
+
+fn handle_post_request(
+    client: &reqwest::Client,
+    url: String,
+    variables: &VariableStore,
+    headers: HeaderMap,
+    req_body: Option<RequestDataBody>,
+) -> Result<RequestBuilder, Box<dyn std::error::Error>> {
+    match req_body {
+        Some(req_body) => {
+            // 1. Create the base JSON data
+            let mut body_data = request_body_data(&req_body)?;
+            resolve_placeholders(&mut body_data, variables)?;
+
+            // 2. Check body type and build request
+            match req_body.body_type.as_str() {
+                "MULTIPART" => {
+                    // Convert JSON payload to a string (this will be one part of the form)
+                    let json_string = serde_json::to_string(&body_data)?;
+
+                    // Start building the multipart form
+                    let mut form = multipart::Form::new();
+
+                    // Add the JSON payload as a text field named 'payload'
+                    form = form.text("payload", json_string);
+
+                    // Add files (if any)
+                    if let Some(file_paths) = req_body.file_paths {
+                        for path_str in file_paths {
+                            let path = Path::new(&path_str);
+                            let filename = path
+                                .file_name()
+                                .unwrap_or_default()
+                                .to_string_lossy()
+                                .into_owned();
+
+                            // Create a file part from the file bytes
+                            let file = std::fs::read(path)?;
+                            let file_part = multipart::Part::bytes(file).file_name(filename);
+
+                            // Add the file part under the field name 'images'
+                            // This corresponds to the 'images' field in your API controller.
+                            form = form.part("images", file_part);
+                        }
+                    }
+
+                    // Build the request with the multipart form
+                    // Use .multipart() instead of .form()
+                    Ok(client.post(url).headers(headers).multipart(form))
+                }
+
+                // ----------------------------------------------------
+                // Existing: Standard form-urlencoded (simple key=value)
+                // ----------------------------------------------------
+                "FORM_DATA" => Ok(client.post(url).headers(headers).form(&body_data)),
+
+                // ----------------------------------------------------
+                // Existing: Standard JSON body
+                // ----------------------------------------------------
+                _ => {
+                    // Assume standard JSON body if not specified
+                    let pretty_json_string = serde_json::to_string_pretty(&body_data)?;
+                    Ok(client.post(url).headers(headers).body(pretty_json_string))
+                }
+            }
+        }
+        None => Ok(client.post(url).headers(headers)),
+    }
+}
+
+fn handle_put_request(
+    client: &reqwest::Client,
+    url: String,
+    variables: &VariableStore,
+    headers: HeaderMap,
+    req_body: Option<RequestDataBody>,
+) -> Result<RequestBuilder, Box<dyn std::error::Error>> {
+    match req_body {
+        Some(req_body) => {
+            let mut body_data = request_body_data(&req_body)?;
+
+            resolve_placeholders(&mut body_data, variables)?;
+
+            if req_body.body_type == "FORM_DATA" {
+                Ok(client.put(url).headers(headers).form(&body_data))
+            } else {
+                let pretty_json_string = serde_json::to_string_pretty(&body_data)?;
+                Ok(client.put(url).headers(headers).body(pretty_json_string))
+            }
+        }
+        None => Ok(client.put(url).headers(headers)),
+    }
+}
+
+fn handle_delete_request(
+    client: &reqwest::Client,
+    url: String,
+    variables: &VariableStore,
+    headers: HeaderMap,
+    req_body: Option<RequestDataBody>,
+) -> Result<RequestBuilder, Box<dyn std::error::Error>> {
+    match req_body {
+        Some(req_body) => {
+            let mut body_data = request_body_data(&req_body)?;
+
+            resolve_placeholders(&mut body_data, variables)?;
+            if req_body.body_type == "FORM_DATA" {
+                // Simplified: Just send the DELETE request without a body here.
+                Ok(client.delete(url).headers(headers))
+            } else {
+                let pretty_json_string = serde_json::to_string_pretty(&body_data)?;
+                // Use .body() to send the JSON content (non-standard for DELETE)
+                Ok(client.delete(url).headers(headers).body(pretty_json_string))
+            }
+        }
+        // If there is no body, it is a standard DELETE request.
+        None => Ok(client.delete(url).headers(headers)),
+    }
+}
+
+async fn send_request(
+    client: &reqwest::Client,
+    method: &str,
+    url: String,
+    headers: HeaderMap,
+    auth: &AuthConfig,
+    variables: &VariableStore,
+    req_body: Option<RequestDataBody>,
+) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+    let builder = build_request(client, method, url, headers, variables, req_body)?;
+    auth.send(client, builder).await
+}
+
+pub(crate) fn build_request(
+    client: &reqwest::Client,
+    method: &str,
+    url: String,
+    headers: HeaderMap,
+    variables: &VariableStore,
+    req_body: Option<RequestDataBody>,
+) -> Result<RequestBuilder, Box<dyn std::error::Error>> {
+    match method {
+        "GET" => handle_get_request(client, url, headers),
+        "POST" => handle_post_request(client, url, variables, headers, req_body),
+        "PUT" => handle_put_request(client, url, variables, headers, req_body),
+        "DELETE" => handle_delete_request(client, url, variables, headers, req_body),
+        _ => Err(format!("Unsupported HTTP method: {}", method).into()),
+    }
+}
+
+async fn make_http_request(
+    client: &reqwest::Client,
+    method: &str,
+    url: String,
+    headers: HeaderMap,
+    auth: &AuthConfig,
+    variables: &VariableStore,
+    req_body: Option<RequestDataBody>,
+) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+    let response = send_request(
+        client,
+        method,
+        url.clone(),
+        headers.clone(),
+        auth,
+        variables,
+        req_body.clone(),
+    )
+    .await?;
+
+    // Digest auth: answer the 401 challenge and send the request again
+    if let Some(digest_auth) = digest_challenge_auth(auth, method, &url, &response)? {
+        return send_request(
+            client,
+            method,
+            url,
+            headers,
+            &digest_auth,
+            variables,
+            req_body,
+        )
+        .await;
+    }
+
+    Ok(response)
+}
+
+/// Send a request saved in the history as it was: method, URL, headers and body.
+/// The auth, client and retry settings come from `request`.
+pub(crate) async fn send_stored_request(
+    app_main_request: &AppMainRequest,
+    request: &RequestData,
+    stored: &HistoryRequest,
+    variables: &VariableStore,
+    cookie_jar: &Option<Arc<CookieStoreMutex>>,
+) -> Result<CapturedResponse, Box<dyn std::error::Error>> {
+    let body_type = request
+        .req_body
+        .as_ref()
+        .map(|body| body.body_type.as_str());
+    if body_type == Some("MULTIPART") {
+        return Err("A multipart request cannot be sent again from the history".into());
+    }
+
+    let client = build_client(app_main_request, request, cookie_jar)?;
+    let auth = select_auth(app_main_request, request, variables)
+        .resolve(&client, variables)
+        .await?;
+    let method = Method::from_bytes(stored.method.as_bytes())?;
+    let mut headers = HeaderMap::new();
+    for (name, value) in &stored.headers {
+        headers.append(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+
+    let build = || -> Result<RequestBuilder, Box<dyn std::error::Error>> {
+        let builder = client
+            .request(method.clone(), &stored.url)
+            .headers(headers.clone());
+        Ok(match &stored.body {
+            Some(body) if body_type == Some("FORM_DATA") => builder.form(body),
+            Some(body) => builder.body(serde_json::to_string_pretty(body)?),
+            None => builder,
+        })
+    };
+    let send = || async {
+        let response = auth.send(&client, build()?).await?;
+        // Digest auth: answer the 401 challenge and send the request again
+        match digest_challenge_auth(&auth, &stored.method, &stored.url, &response)? {
+            Some(digest_auth) => digest_auth.send(&client, build()?).await,
+            None => Ok(response),
+        }
+    };
+    let response = match &request.retry {
+        Some(retry) => retry.run(send).await?,
+        None => send().await?,
+    };
+    Ok(CapturedResponse::read(response).await?)
+}
+
+/// With digest auth, the auth answering the challenge of a 401 response, None otherwise
+pub(crate) fn digest_challenge_auth(
+    auth: &AuthConfig,
+    method: &str,
+    url: &str,
+    response: &reqwest::Response,
+) -> Result<Option<AuthConfig>, Box<dyn std::error::Error>> {
+    let AuthConfig::Digest { username, password } = auth else {
+        return Ok(None);
+    };
+    let challenge = response
+        .headers()
+        .get(WWW_AUTHENTICATE)
+        .and_then(|value| value.to_str().ok());
+
+    match (response.status(), challenge) {
+        (StatusCode::UNAUTHORIZED, Some(challenge)) => Ok(Some(AuthConfig::ApiKey {
+            name: AUTHORIZATION.to_string(),
+            value: digest_authorization(method, url, username, password, challenge)?,
+            location: ApiKeyLocation::Header,
+        })),
+        _ => Ok(None),
+    }
+}
+
+/// Scans a JSON object for strings like "{{VAR_NAME}}" and replaces them
+/// with the VAR_NAME variable (a --var override or variable_dir/VAR_NAME.txt)
+pub fn resolve_placeholders(
+    json_body: &mut Value,
+    variables: &VariableStore,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // We only look inside JSON Objects (Key-Value pairs)
+    if let Value::Object(ref mut map) = json_body {
+        for (_key, value) in map {
+            // We only care if the value is a String
+            if let Value::String(val_str) = value {
+                if val_str.starts_with("{{") && val_str.ends_with("}}") {
+                    // 1. Extract variable name (e.g., "REFRESH_TOKEN")
+                    let var_name = &val_str[2..val_str.len() - 2];
+
+                    // 2. Read the variable
+                    let content = variables.get(var_name)?;
+
+                    // 3. Update the JSON value directly
+                    *val_str = content;
+                }
+            }
+        }
+    }
+    Ok(())
+}
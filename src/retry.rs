@@ -0,0 +1,238 @@
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use rand::Rng;
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    Response,
+};
+use serde::Deserialize;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Retry policy for a single request
+#[derive(Clone, Debug, Deserialize)]
+pub struct RetryConfig {
+    #[serde(default = "default_max_attempts")]
+    max_attempts: u32,
+    #[serde(default = "default_on_status")]
+    on_status: Vec<u16>,
+    #[serde(default = "default_on_error")]
+    on_error: Vec<RetryErrorKind>,
+    #[serde(default = "default_base_delay_ms")]
+    base_delay_ms: u64,
+    #[serde(default = "default_max_delay_ms")]
+    max_delay_ms: u64,
+    #[serde(default = "default_respect_retry_after")]
+    respect_retry_after: bool,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryErrorKind {
+    Connect,
+    Timeout,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_on_status() -> Vec<u16> {
+    vec![502, 503, 504]
+}
+
+fn default_on_error() -> Vec<RetryErrorKind> {
+    vec![RetryErrorKind::Connect]
+}
+
+fn default_base_delay_ms() -> u64 {
+    200
+}
+
+fn default_max_delay_ms() -> u64 {
+    5000
+}
+
+fn default_respect_retry_after() -> bool {
+    true
+}
+
+impl RetryConfig {
+    /// Call `send` until it succeeds, fails with something not retryable,
+    /// or runs out of attempts. Every attempt is printed.
+    pub async fn run<F, Fut>(&self, mut send: F) -> Result<Response, Box<dyn std::error::Error>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<Response, Box<dyn std::error::Error>>>,
+    {
+        let max_attempts = self.max_attempts.max(1);
+        let mut attempt = 1;
+
+        loop {
+            let result = send().await;
+            let (retryable, outcome, retry_after) = match &result {
+                Ok(response) => (
+                    self.on_status.contains(&response.status().as_u16()),
+                    response.status().to_string(),
+                    retry_after(response.headers()),
+                ),
+                Err(err) => (self.retries_error(err.as_ref()), err.to_string(), None),
+            };
+
+            let label = format!("{}/{}", attempt, max_attempts);
+            if !retryable {
+                eprintln!(
+                    "{} {} {}",
+                    "ATTEMPT  :".blue().bold(),
+                    label,
+                    outcome.green()
+                );
+                return result;
+            }
+            if attempt >= max_attempts {
+                eprintln!("{} {} {}", "ATTEMPT  :".blue().bold(), label, outcome.red());
+                return result;
+            }
+
+            let Some(delay) = self.delay(attempt, retry_after) else {
+                eprintln!(
+                    "{} {} {} {}",
+                    "ATTEMPT  :".blue().bold(),
+                    label,
+                    outcome.red(),
+                    "(Retry-After is longer than max_delay_ms)".red()
+                );
+                return result;
+            };
+            eprintln!(
+                "{} {} {} {}",
+                "ATTEMPT  :".blue().bold(),
+                label,
+                outcome.yellow(),
+                format!("(retrying in {}ms)", delay.as_millis()).yellow()
+            );
+            sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    fn retries_error(&self, err: &(dyn std::error::Error + 'static)) -> bool {
+        match err.downcast_ref::<reqwest::Error>() {
+            Some(err) => {
+                (err.is_connect() && self.on_error.contains(&RetryErrorKind::Connect))
+                    || (err.is_timeout() && self.on_error.contains(&RetryErrorKind::Timeout))
+            }
+            None => false,
+        }
+    }
+
+    /// Exponential backoff with equal jitter capped at `max_delay_ms`, or the server's
+    /// `Retry-After`. None when the server asks to wait longer than `max_delay_ms`.
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if let (true, Some(retry_after)) = (self.respect_retry_after, retry_after) {
+            return (retry_after <= Duration::from_millis(self.max_delay_ms))
+                .then_some(retry_after);
+        }
+
+        let backoff = self
+            .base_delay_ms
+            .saturating_mul(2u64.saturating_pow(attempt - 1))
+            .min(self.max_delay_ms);
+        let jitter = rand::thread_rng().gen_range(0..=backoff / 2);
+        Some(Duration::from_millis(backoff - backoff / 2 + jitter))
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date, a date in the past means now
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn config(respect_retry_after: bool) -> RetryConfig {
+        serde_json::from_value(serde_json::json!({
+            "base_delay_ms": 100,
+            "max_delay_ms": 1000,
+            "respect_retry_after": respect_retry_after
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn backoff_doubles_with_jitter_up_to_the_max_delay() {
+        let config = config(true);
+        for (attempt, backoff) in [
+            (1, 100),
+            (2, 200),
+            (3, 400),
+            (4, 800),
+            (5, 1000),
+            (20, 1000),
+        ] {
+            for _ in 0..50 {
+                let delay = config.delay(attempt, None).unwrap().as_millis() as u64;
+                assert!(
+                    (backoff - backoff / 2..=backoff).contains(&delay),
+                    "attempt {} waited {}ms",
+                    attempt,
+                    delay
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn retry_after_wins_over_backoff_unless_it_is_too_long() {
+        let config = config(true);
+        assert_eq!(
+            config.delay(1, Some(Duration::from_millis(700))),
+            Some(Duration::from_millis(700))
+        );
+        assert_eq!(config.delay(1, Some(Duration::from_secs(30))), None);
+
+        let ignoring = self::config(false);
+        let delay = ignoring.delay(1, Some(Duration::from_secs(30))).unwrap();
+        assert!(delay <= Duration::from_millis(100));
+    }
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_is_read_as_seconds_or_an_http_date() {
+        assert_eq!(retry_after(&headers("30")), Some(Duration::from_secs(30)));
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+
+        let at = Utc::now() + chrono::Duration::seconds(120);
+        let delay = retry_after(&headers(
+            &at.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+        ))
+        .unwrap();
+        assert!(delay > Duration::from_secs(115) && delay <= Duration::from_secs(120));
+
+        assert_eq!(
+            retry_after(&headers("Sun, 06 Nov 1994 08:49:37 GMT")),
+            Some(Duration::ZERO)
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use colored::Colorize;
+use jsonschema::Draft;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::Path;
+
+use crate::file_ops::read_from_file;
+
+/// The JSON Schema a response body must match, set with `"schema": { ... }` on a request.
+///
+/// `{"file": "schemas/user.json"}` uses a JSON Schema file,
+/// `{"component": "User"}` uses `#/components/schemas/User` of the collection `openapi` file
+/// (or of `file` when both are set). JSON and YAML documents are read.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SchemaConfig {
+    file: Option<String>,
+    component: Option<String>,
+}
+
+/// A part of the body that does not match the schema
+pub struct SchemaViolation {
+    /// JSON pointer to the value, e.g. `/data/0/id`
+    pub path: String,
+    pub reason: String,
+}
+
+/// Read a JSON, or a `.yaml`/`.yml`, document
+fn read_document(file: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    let content = read_from_file(file).map_err(|e| format!("Schema {}: {}", file, e))?;
+    let is_yaml = Path::new(file).extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("yaml") || extension.eq_ignore_ascii_case("yml")
+    });
+
+    let document = if is_yaml {
+        serde_yaml::from_str(&content).map_err(|e| format!("Schema {}: {}", file, e))?
+    } else {
+        serde_json::from_str(&content).map_err(|e| format!("Schema {}: {}", file, e))?
+    };
+    Ok(document)
+}
+
+/// OpenAPI 3.0 marks a schema that also accepts null with `nullable: true`, which JSON Schema
+/// ignores. Rewrite it as JSON Schema: `"type": ["string", "null"]`, null added to an `enum`,
+/// or `anyOf` with `{"type": "null"}` for a schema without a type such as a `$ref`.
+fn translate_nullable(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::Bool(nullable)) = map.get("nullable") {
+                let nullable = *nullable;
+                map.remove("nullable");
+                if nullable {
+                    if let Some(Value::Array(values)) = map.get_mut("enum") {
+                        if !values.contains(&Value::Null) {
+                            values.push(Value::Null);
+                        }
+                    }
+                    match map.get_mut("type") {
+                        Some(Value::String(kind)) => {
+                            let kind = Value::String(kind.clone());
+                            map.insert("type".to_string(), json!([kind, "null"]));
+                        }
+                        Some(Value::Array(kinds)) => {
+                            if !kinds.contains(&json!("null")) {
+                                kinds.push(json!("null"));
+                            }
+                        }
+                        _ => {
+                            let schema = Value::Object(std::mem::take(map));
+                            map.insert("anyOf".to_string(), json!([schema, {"type": "null"}]));
+                        }
+                    }
+                }
+            }
+            map.values_mut().for_each(translate_nullable);
+        }
+        Value::Array(items) => items.iter_mut().for_each(translate_nullable),
+        _ => {}
+    }
+}
+
+impl SchemaConfig {
+    /// The schema to validate with. A component is referenced from its whole document,
+    /// so the `$ref`s between components resolve.
+    pub fn load(&self, openapi: Option<&str>) -> Result<Value, Box<dyn std::error::Error>> {
+        let Some(component) = &self.component else {
+            return match &self.file {
+                Some(file) => read_document(file),
+                None => Err("A schema needs a \"file\" or a \"component\"".into()),
+            };
+        };
+
+        let file = self
+            .file
+            .as_deref()
+            .or(openapi)
+            .ok_or("A schema component needs the collection \"openapi\" file")?;
+        let mut document = read_document(file)?;
+        let is_openapi_3_0 = document
+            .get("openapi")
+            .and_then(Value::as_str)
+            .is_some_and(|version| version.starts_with("3.0"));
+        if is_openapi_3_0 {
+            translate_nullable(&mut document);
+        }
+        if document
+            .pointer(&format!("/components/schemas/{}", component))
+            .is_none()
+        {
+            return Err(format!("Component '{}' not found in {}", component, file).into());
+        }
+
+        let object = document
+            .as_object_mut()
+            .ok_or_else(|| format!("{} is not an OpenAPI document", file))?;
+        object.insert(
+            "$ref".to_string(),
+            Value::String(format!("#/components/schemas/{}", component)),
+        );
+        Ok(document)
+    }
+
+    /// Every violation of the schema by `body`, empty when it matches
+    pub fn validate(
+        &self,
+        openapi: Option<&str>,
+        body: &Value,
+    ) -> Result<Vec<SchemaViolation>, Box<dyn std::error::Error>> {
+        let schema = self.load(openapi)?;
+        let validator = jsonschema::options()
+            .with_draft(Draft::Draft202012)
+            .build(&schema)
+            .map_err(|e| format!("Invalid schema: {}", e))?;
+
+        Ok(validator
+            .iter_errors(body)
+            .map(|error| SchemaViolation {
+                path: error.instance_path.to_string(),
+                reason: error.to_string(),
+            })
+            .collect())
+    }
+}
+
+/// Print whether the body matched its schema, with one line per violation
+pub fn display_schema_violations(violations: &[SchemaViolation]) {
+    if violations.is_empty() {
+        eprintln!("{} {}", "SCHEMA   :".blue().bold(), "valid".green());
+        return;
+    }
+
+    eprintln!(
+        "{} {}",
+        "SCHEMA   :".blue().bold(),
+        format!("{} violations", violations.len()).red()
+    );
+    for violation in violations {
+        let path = if violation.path.is_empty() {
+            "(body)"
+        } else {
+            &violation.path
+        };
+        eprintln!("  {} {}", format!("{}:", path).yellow(), violation.reason);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn nullable_becomes_a_null_type() {
+        let mut schema = json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "nullable": true},
+                "role": {"type": "string", "enum": ["admin", "user"], "nullable": true},
+                "team": {"$ref": "#/components/schemas/Team", "nullable": true},
+                "age": {"type": "integer", "nullable": false}
+            }
+        });
+        translate_nullable(&mut schema);
+        assert_eq!(
+            schema["properties"],
+            json!({
+                "name": {"type": ["string", "null"]},
+                "role": {"type": ["string", "null"], "enum": ["admin", "user", null]},
+                "team": {"anyOf": [{"$ref": "#/components/schemas/Team"}, {"type": "null"}]},
+                "age": {"type": "integer"}
+            })
+        );
+    }
+
+    #[test]
+    fn openapi_3_0_components_accept_null_when_nullable() {
+        let file =
+            std::env::temp_dir().join(format!("rest_api_nullable_{}.yaml", std::process::id()));
+        let document = r#"
+openapi: 3.0.3
+components:
+  schemas:
+    User:
+      type: object
+      required: [name]
+      properties:
+        name:
+          type: string
+          nullable: true
+"#;
+        fs::write(&file, document).unwrap();
+        let config = SchemaConfig {
+            file: None,
+            component: Some("User".to_string()),
+        };
+        let openapi = file.to_string_lossy();
+
+        let violations = config
+            .validate(Some(&openapi), &json!({"name": null}))
+            .unwrap();
+        assert!(violations.is_empty());
+        let violations = config
+            .validate(Some(&openapi), &json!({"name": 1}))
+            .unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].path, "/name");
+    }
+}
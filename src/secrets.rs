@@ -0,0 +1,201 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::RngCore;
+use serde::Deserialize;
+use sha2::Sha256;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::file_ops::{lock_for, read_from_file, write_atomic_locked};
+
+const SALT_FILE: &str = ".secrets_salt";
+const KDF_ROUNDS: u32 = 100_000;
+const ENCRYPTED_PREFIX: &str = "v1:";
+
+/// Shown instead of a secret value
+pub const MASK: &str = "********";
+
+/// Encrypted storage for secret variables, used through `"secrets": { ... }`
+///
+/// Variables matching `variables` are saved as `NAME.enc` instead of `NAME.txt`,
+/// encrypted with a key derived from `key_file` or the `passphrase_env` variable,
+/// and masked in every console output.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SecretsConfig {
+    key_file: Option<String>,
+    #[serde(default = "default_passphrase_env")]
+    passphrase_env: String,
+    #[serde(default = "default_secret_variables")]
+    variables: Vec<String>,
+}
+
+fn default_passphrase_env() -> String {
+    "REST_API_PASSPHRASE".to_string()
+}
+
+fn default_secret_variables() -> Vec<String> {
+    vec![
+        "*TOKEN*".to_string(),
+        "*SECRET*".to_string(),
+        "*PASSWORD*".to_string(),
+    ]
+}
+
+/// Match a name against a pattern where `*` stands for any text, ignoring case
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let (pattern, name) = (pattern.to_lowercase(), name.to_lowercase());
+    let (pattern, name) = (pattern.as_str(), name.as_str());
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || name.len() < first.len() + last.len() {
+        return false;
+    }
+
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    name.ends_with(last)
+}
+
+impl SecretsConfig {
+    pub fn is_secret(&self, name: &str) -> bool {
+        self.variables
+            .iter()
+            .any(|pattern| matches_pattern(pattern, name))
+    }
+
+    /// Derive the encryption key from the key file or the passphrase, salted per variable_dir
+    pub fn derive_key(&self, variable_dir: &Path) -> io::Result<[u8; 32]> {
+        let secret = match &self.key_file {
+            Some(key_file) => read_from_file(key_file)?.trim().to_string(),
+            None => env::var(&self.passphrase_env).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "Secret variables need a passphrase: set {} or secrets.key_file",
+                        self.passphrase_env
+                    ),
+                )
+            })?,
+        };
+
+        // Locked, so parallel first runs agree on one salt
+        let salt_path = variable_dir.join(SALT_FILE);
+        let lock = lock_for(&salt_path)?;
+        let salt = match fs::read(&salt_path) {
+            Ok(salt) => salt,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let mut salt = vec![0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                write_atomic_locked(&salt_path, &salt, &lock)?;
+                salt
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(secret.as_bytes(), &salt, KDF_ROUNDS, &mut key);
+        Ok(key)
+    }
+}
+
+/// Encrypt a value as `v1:` followed by the base64 nonce and ciphertext
+pub fn encrypt(key: &[u8; 32], plaintext: &str) -> io::Result<String> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|_| io::Error::other("Failed to encrypt secret variable"))?;
+
+    let mut data = nonce.to_vec();
+    data.extend(ciphertext);
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(data)))
+}
+
+/// Decrypt a value written by `encrypt`
+pub fn decrypt(key: &[u8; 32], text: &str) -> io::Result<String> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let data = text
+        .trim()
+        .strip_prefix(ENCRYPTED_PREFIX)
+        .ok_or_else(|| invalid("Unknown secret variable format"))?;
+    let data = STANDARD
+        .decode(data)
+        .map_err(|_| invalid("Secret variable is not valid base64"))?;
+    if data.len() < 12 {
+        return Err(invalid("Secret variable is too short"));
+    }
+
+    let (nonce, ciphertext) = data.split_at(12);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| invalid("Wrong passphrase or corrupted secret variable"))?;
+    String::from_utf8(plaintext).map_err(|_| invalid("Secret variable is not valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_match_names_ignoring_case() {
+        assert!(matches_pattern("*TOKEN*", "access_token"));
+        assert!(matches_pattern("*TOKEN*", "TOKEN"));
+        assert!(matches_pattern("api_*_KEY", "API_stripe_key"));
+        assert!(matches_pattern("password", "PASSWORD"));
+        assert!(!matches_pattern("*TOKEN", "token_expires_at"));
+        assert!(!matches_pattern("api_*_key", "api_key"));
+    }
+
+    fn config_with_key_file(dir: &Path, secret: &str) -> SecretsConfig {
+        let key_file = dir.join(format!("{}.key", secret));
+        fs::write(&key_file, secret).unwrap();
+        serde_json::from_value(serde_json::json!({"key_file": key_file.to_string_lossy()})).unwrap()
+    }
+
+    #[test]
+    fn encrypted_values_decrypt_only_with_the_same_key() {
+        let dir = env::temp_dir().join(format!("rest_api_secrets_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let key = config_with_key_file(&dir, "right")
+            .derive_key(&dir)
+            .unwrap();
+        let encrypted = encrypt(&key, "s3cr3t value").unwrap();
+        assert!(encrypted.starts_with(ENCRYPTED_PREFIX));
+        assert!(!encrypted.contains("s3cr3t"));
+        assert_ne!(encrypted, encrypt(&key, "s3cr3t value").unwrap());
+
+        // The salt is kept, so the key is derived again the same way
+        let same_key = config_with_key_file(&dir, "right")
+            .derive_key(&dir)
+            .unwrap();
+        assert_eq!(decrypt(&same_key, &encrypted).unwrap(), "s3cr3t value");
+
+        let wrong_key = config_with_key_file(&dir, "wrong")
+            .derive_key(&dir)
+            .unwrap();
+        let error = decrypt(&wrong_key, &encrypted).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("Wrong passphrase"));
+
+        assert!(decrypt(&key, "plain text").is_err());
+        assert!(decrypt(&key, "v1:AAAA").is_err());
+    }
+}
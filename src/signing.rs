@@ -0,0 +1,417 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{
+    header::{HeaderName, HeaderValue, AUTHORIZATION, HOST},
+    Request,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::variables::VariableStore;
+
+/// HMAC signing, used through `"auth": { "type": "hmac", ... }`
+///
+/// The signed string is the method, the path with query, the timestamp, each
+/// `signed_headers` entry as `name:value` and the hex SHA-256 of the body,
+/// joined with newlines.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HmacConfig {
+    key_id: Option<String>,
+    secret: String,
+    #[serde(default)]
+    algorithm: HmacAlgorithm,
+    #[serde(default)]
+    encoding: SignatureEncoding,
+    #[serde(default)]
+    signed_headers: Vec<String>,
+    #[serde(default = "default_signature_header")]
+    signature_header: String,
+    #[serde(default = "default_timestamp_header")]
+    timestamp_header: String,
+    #[serde(default = "default_key_id_header")]
+    key_id_header: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HmacAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+/// AWS Signature Version 4, used through `"auth": { "type": "aws_sigv4", ... }`
+#[derive(Clone, Debug, Deserialize)]
+pub struct AwsSigV4Config {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+    region: String,
+    service: String,
+}
+
+fn default_signature_header() -> String {
+    "X-Signature".to_string()
+}
+
+fn default_timestamp_header() -> String {
+    "X-Timestamp".to_string()
+}
+
+fn default_key_id_header() -> String {
+    "X-Key-Id".to_string()
+}
+
+/// Only buffered bodies can be signed, multipart file uploads are streamed
+fn request_body(request: &Request) -> Result<&[u8], Box<dyn std::error::Error>> {
+    match request.body() {
+        Some(body) => Ok(body
+            .as_bytes()
+            .ok_or("Streaming request bodies (MULTIPART) cannot be signed")?),
+        None => Ok(&[]),
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn set_header(
+    request: &mut Request,
+    name: &str,
+    value: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    request.headers_mut().insert(
+        HeaderName::from_bytes(name.as_bytes())?,
+        HeaderValue::from_str(value)?,
+    );
+    Ok(())
+}
+
+impl HmacConfig {
+    /// Replace "{{VAR_NAME}}" placeholders in the key id and secret
+    pub fn resolve(
+        &self,
+        variables: &VariableStore,
+    ) -> Result<HmacConfig, Box<dyn std::error::Error>> {
+        Ok(HmacConfig {
+            key_id: self
+                .key_id
+                .as_deref()
+                .map(|key_id| variables.resolve_str(key_id))
+                .transpose()?,
+            secret: variables.resolve_str(&self.secret)?,
+            ..self.clone()
+        })
+    }
+
+    /// Add the timestamp, key id and signature headers to the final request
+    pub fn sign(&self, request: &mut Request) -> Result<(), Box<dyn std::error::Error>> {
+        self.sign_at(request, Utc::now())
+    }
+
+    fn sign_at(
+        &self,
+        request: &mut Request,
+        now: DateTime<Utc>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let timestamp = now.timestamp().to_string();
+        set_header(request, &self.timestamp_header, &timestamp)?;
+
+        let url = request.url();
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+
+        let mut lines = vec![request.method().to_string(), path, timestamp];
+        for name in &self.signed_headers {
+            let value = request
+                .headers()
+                .get(name.as_str())
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            lines.push(format!("{}:{}", name.to_lowercase(), value.trim()));
+        }
+        lines.push(hex::encode(Sha256::digest(request_body(request)?)));
+        let string_to_sign = lines.join("\n");
+
+        let signature = match self.algorithm {
+            HmacAlgorithm::Sha256 => hmac_sha256(self.secret.as_bytes(), string_to_sign.as_bytes()),
+            HmacAlgorithm::Sha512 => hmac_sha512(self.secret.as_bytes(), string_to_sign.as_bytes()),
+        };
+        let signature = match self.encoding {
+            SignatureEncoding::Hex => hex::encode(signature),
+            SignatureEncoding::Base64 => STANDARD.encode(signature),
+        };
+
+        if let Some(key_id) = &self.key_id {
+            set_header(request, &self.key_id_header, key_id)?;
+        }
+        set_header(request, &self.signature_header, &signature)
+    }
+}
+
+impl AwsSigV4Config {
+    /// Replace "{{VAR_NAME}}" placeholders in the credentials
+    pub fn resolve(
+        &self,
+        variables: &VariableStore,
+    ) -> Result<AwsSigV4Config, Box<dyn std::error::Error>> {
+        Ok(AwsSigV4Config {
+            access_key: variables.resolve_str(&self.access_key)?,
+            secret_key: variables.resolve_str(&self.secret_key)?,
+            session_token: self
+                .session_token
+                .as_deref()
+                .map(|token| variables.resolve_str(token))
+                .transpose()?,
+            ..self.clone()
+        })
+    }
+
+    /// Add the `x-amz-*` and Authorization headers to the final request
+    pub fn sign(&self, request: &mut Request) -> Result<(), Box<dyn std::error::Error>> {
+        self.sign_at(request, Utc::now())
+    }
+
+    fn sign_at(
+        &self,
+        request: &mut Request,
+        now: DateTime<Utc>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date_stamp = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(request_body(request)?));
+
+        let url = request.url().clone();
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        set_header(request, HOST.as_str(), &host)?;
+        set_header(request, "x-amz-date", &amz_date)?;
+        // Only S3 requires the payload hash header
+        if self.service == "s3" {
+            set_header(request, "x-amz-content-sha256", &payload_hash)?;
+        }
+        if let Some(session_token) = &self.session_token {
+            set_header(request, "x-amz-security-token", session_token)?;
+        }
+
+        // Host, content-type and every x-amz-* header are signed
+        let mut headers: Vec<(String, String)> = request
+            .headers()
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.as_str();
+                name == "host" || name == "content-type" || name.starts_with("x-amz-")
+            })
+            .map(|(name, value)| {
+                let value = value.to_str().unwrap_or_default();
+                let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                (name.as_str().to_string(), value)
+            })
+            .collect();
+        headers.sort();
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(";");
+
+        // S3 paths are encoded once, every other service encodes them twice
+        let canonical_uri = if self.service == "s3" {
+            url.path().to_string()
+        } else {
+            aws_uri_encode(url.path(), false)
+        };
+
+        let mut query: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(key, value)| (aws_uri_encode(&key, true), aws_uri_encode(&value, true)))
+            .collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            request.method(),
+            canonical_uri,
+            canonical_query,
+            canonical_headers,
+            signed_headers,
+            payload_hash
+        );
+
+        let scope = format!(
+            "{}/{}/{}/aws4_request",
+            date_stamp, self.region, self.service
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key_date = hmac_sha256(
+            format!("AWS4{}", self.secret_key).as_bytes(),
+            date_stamp.as_bytes(),
+        );
+        let key_region = hmac_sha256(&key_date, self.region.as_bytes());
+        let key_service = hmac_sha256(&key_region, self.service.as_bytes());
+        let signing_key = hmac_sha256(&key_service, b"aws4_request");
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        );
+        set_header(request, AUTHORIZATION.as_str(), &authorization)
+    }
+}
+
+/// URI-encode everything except the unreserved characters, as AWS expects
+fn aws_uri_encode(text: &str, encode_slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn aws_config(service: &str) -> AwsSigV4Config {
+        AwsSigV4Config {
+            access_key: "AKIDEXAMPLE".to_string(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+            region: "us-east-1".to_string(),
+            service: service.to_string(),
+        }
+    }
+
+    fn header<'a>(request: &'a Request, name: &str) -> &'a str {
+        request.headers()[name].to_str().unwrap()
+    }
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        // Test case 2: key "Jefe"
+        let data = b"what do ya want for nothing?";
+        assert_eq!(
+            hex::encode(hmac_sha256(b"Jefe", data)),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex::encode(hmac_sha512(b"Jefe", data)),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+             9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+        );
+    }
+
+    #[test]
+    fn hmac_signs_method_path_timestamp_headers_and_body() {
+        let config: HmacConfig = serde_json::from_value(serde_json::json!({
+            "key_id": "client-1",
+            "secret": "Jefe",
+            "signed_headers": ["Content-Type"]
+        }))
+        .unwrap();
+        let mut request = reqwest::Client::new()
+            .post("https://api.example.com/orders?id=7")
+            .header("content-type", "application/json")
+            .body("{}")
+            .build()
+            .unwrap();
+
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        config.sign_at(&mut request, now).unwrap();
+
+        let string_to_sign = format!(
+            "POST\n/orders?id=7\n1700000000\ncontent-type:application/json\n{}",
+            hex::encode(Sha256::digest(b"{}"))
+        );
+        assert_eq!(header(&request, "x-timestamp"), "1700000000");
+        assert_eq!(header(&request, "x-key-id"), "client-1");
+        assert_eq!(
+            header(&request, "x-signature"),
+            hex::encode(hmac_sha256(b"Jefe", string_to_sign.as_bytes()))
+        );
+    }
+
+    #[test]
+    fn sigv4_matches_the_aws_get_vanilla_vector() {
+        let mut request = reqwest::Client::new()
+            .get("https://example.amazonaws.com/")
+            .build()
+            .unwrap();
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        aws_config("service").sign_at(&mut request, now).unwrap();
+
+        assert_eq!(
+            header(&request, "authorization"),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn sigv4_matches_the_aws_iam_list_users_example() {
+        let mut request = reqwest::Client::new()
+            .get("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08")
+            .header(
+                "content-type",
+                "application/x-www-form-urlencoded; charset=utf-8",
+            )
+            .build()
+            .unwrap();
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        aws_config("iam").sign_at(&mut request, now).unwrap();
+
+        assert_eq!(header(&request, "x-amz-date"), "20150830T123600Z");
+        assert_eq!(
+            header(&request, "authorization"),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+    }
+}
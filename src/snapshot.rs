@@ -0,0 +1,499 @@
+use colored::Colorize;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::file_ops::{expand_tilde, resolve_relative, safe_file_name, write_atomic};
+
+/// Written in place of an ignored value, so the key itself is still compared
+const IGNORED: &str = "<ignored>";
+
+/// Where `--snapshot` saves response bodies and which paths it leaves out,
+/// set with `"snapshot": { ... }` on the collection or on a request.
+///
+/// `ignore` takes body paths such as `data.created_at`, where `*` stands for one key or index
+/// and `**` for any number of them: `data.*.id`, `**.updated_at`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SnapshotConfig {
+    dir: Option<String>,
+    #[serde(default)]
+    ignore: Vec<String>,
+}
+
+impl SnapshotConfig {
+    /// The request settings on top of the collection ones, ignored paths add up
+    pub fn merge(&self, other: &SnapshotConfig) -> SnapshotConfig {
+        let mut ignore = self.ignore.clone();
+        ignore.extend(other.ignore.iter().cloned());
+        SnapshotConfig {
+            dir: other.dir.clone().or_else(|| self.dir.clone()),
+            ignore,
+        }
+    }
+
+    /// Look a relative `dir` up next to the collection first
+    pub(crate) fn resolve_files(&mut self, collection_dir: &Path) {
+        if let Some(dir) = &self.dir {
+            self.dir = Some(resolve_relative(collection_dir, dir));
+        }
+    }
+
+    /// The snapshot file of `name`, under `dir` or `default_dir`
+    pub fn path(&self, default_dir: &Path, name: &str) -> PathBuf {
+        let dir = match &self.dir {
+            Some(dir) => PathBuf::from(expand_tilde(dir)),
+            None => default_dir.to_path_buf(),
+        };
+        dir.join(format!("{}.json", safe_file_name(name)))
+    }
+
+    /// Compare a response body with its snapshot, the first run or `update` writes it
+    pub fn check(&self, path: &Path, body: &Value, update: bool) -> io::Result<SnapshotOutcome> {
+        let body = normalize(body, &self.ignore, &mut Vec::new());
+
+        let saved = match fs::read_to_string(path) {
+            Ok(saved) => Some(
+                serde_json::from_str::<Value>(&saved)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            ),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        let differences = match &saved {
+            Some(saved) => differences(saved, &body),
+            None => Vec::new(),
+        };
+        if saved.is_some() && differences.is_empty() {
+            return Ok(SnapshotOutcome::Matched);
+        }
+        if saved.is_some() && !update {
+            return Ok(SnapshotOutcome::Changed(differences));
+        }
+
+        let contents = serde_json::to_string_pretty(&body)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_atomic(path, format!("{}\n", contents).as_bytes())?;
+        Ok(match saved {
+            Some(_) => SnapshotOutcome::Updated(differences),
+            None => SnapshotOutcome::Written,
+        })
+    }
+}
+
+/// How a response compared with its snapshot
+pub enum SnapshotOutcome {
+    /// No snapshot yet, it was saved
+    Written,
+    Matched,
+    /// Different and replaced with `--update-snapshots`
+    Updated(Vec<Difference>),
+    /// Different, the snapshot was kept
+    Changed(Vec<Difference>),
+}
+
+impl SnapshotOutcome {
+    pub fn failed(&self) -> bool {
+        matches!(self, SnapshotOutcome::Changed(_))
+    }
+}
+
+/// One difference between a snapshot and a response, at a body path
+#[derive(Debug, PartialEq)]
+pub enum Difference {
+    Added(String, Value),
+    Removed(String, Value),
+    Changed(String, Value, Value),
+}
+
+impl Difference {
+    /// The difference as one indented line, `+`, `-` or `~` followed by its path
+    pub fn line(&self) -> String {
+        let path_or_root = |path: &str| {
+            if path.is_empty() {
+                "(body)".to_string()
+            } else {
+                path.to_string()
+            }
+        };
+        match self {
+            Difference::Added(path, value) => format!(
+                "  {} {}: {}",
+                "+".green(),
+                path_or_root(path),
+                value.to_string().green()
+            ),
+            Difference::Removed(path, value) => format!(
+                "  {} {}: {}",
+                "-".red(),
+                path_or_root(path),
+                value.to_string().red()
+            ),
+            Difference::Changed(path, saved, current) => format!(
+                "  {} {}: {} -> {}",
+                "~".yellow(),
+                path_or_root(path),
+                saved.to_string().red(),
+                current.to_string().green()
+            ),
+        }
+    }
+}
+
+/// Replace the values at ignored paths, objects keep their sorted keys
+fn normalize(value: &Value, ignore: &[String], path: &mut Vec<String>) -> Value {
+    if ignore.iter().any(|pattern| matches_path(pattern, path)) {
+        return Value::String(IGNORED.to_string());
+    }
+
+    match value {
+        Value::Object(map) => {
+            let mut normalized = Map::new();
+            for (key, item) in map {
+                path.push(key.clone());
+                normalized.insert(key.clone(), normalize(item, ignore, path));
+                path.pop();
+            }
+            Value::Object(normalized)
+        }
+        Value::Array(items) => {
+            let mut normalized = Vec::new();
+            for (index, item) in items.iter().enumerate() {
+                path.push(index.to_string());
+                normalized.push(normalize(item, ignore, path));
+                path.pop();
+            }
+            Value::Array(normalized)
+        }
+        other => other.clone(),
+    }
+}
+
+/// Match a dotted pattern where `*` is one segment and `**` any number of them
+fn matches_path(pattern: &str, path: &[String]) -> bool {
+    fn matches(pattern: &[&str], path: &[String]) -> bool {
+        match pattern.split_first() {
+            None => path.is_empty(),
+            Some((&"**", rest)) => (0..=path.len()).any(|skip| matches(rest, &path[skip..])),
+            Some((segment, rest)) => match path.split_first() {
+                Some((first, path_rest)) => {
+                    (*segment == "*" || segment == first) && matches(rest, path_rest)
+                }
+                None => false,
+            },
+        }
+    }
+
+    let pattern: Vec<&str> = pattern.split('.').collect();
+    !path.is_empty() && matches(&pattern, path)
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// What was added, removed or changed from `saved` to `current`
+pub fn differences(saved: &Value, current: &Value) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff(saved, current, "", &mut differences);
+    differences
+}
+
+/// Walk both values and list what was added, removed or changed
+fn diff(saved: &Value, current: &Value, path: &str, differences: &mut Vec<Difference>) {
+    match (saved, current) {
+        (Value::Object(saved), Value::Object(current)) => {
+            for (key, saved_item) in saved {
+                let child = child_path(path, key);
+                match current.get(key) {
+                    Some(current_item) => diff(saved_item, current_item, &child, differences),
+                    None => differences.push(Difference::Removed(child, saved_item.clone())),
+                }
+            }
+            for (key, current_item) in current {
+                if !saved.contains_key(key) {
+                    differences.push(Difference::Added(
+                        child_path(path, key),
+                        current_item.clone(),
+                    ));
+                }
+            }
+        }
+        (Value::Array(saved), Value::Array(current)) => {
+            for index in 0..saved.len().max(current.len()) {
+                let child = child_path(path, &index.to_string());
+                match (saved.get(index), current.get(index)) {
+                    (Some(saved_item), Some(current_item)) => {
+                        diff(saved_item, current_item, &child, differences)
+                    }
+                    (Some(saved_item), None) => {
+                        differences.push(Difference::Removed(child, saved_item.clone()))
+                    }
+                    (None, Some(current_item)) => {
+                        differences.push(Difference::Added(child, current_item.clone()))
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+        (saved, current) if saved != current => differences.push(Difference::Changed(
+            path.to_string(),
+            saved.clone(),
+            current.clone(),
+        )),
+        _ => {}
+    }
+}
+
+/// Print the outcome of one snapshot, with its differences
+pub fn display_snapshot(name: &str, path: &Path, outcome: &SnapshotOutcome) {
+    let (status, differences) = match outcome {
+        SnapshotOutcome::Written => (format!("written {}", path.display()).yellow(), None),
+        SnapshotOutcome::Matched => ("matches".green(), None),
+        SnapshotOutcome::Updated(differences) => (
+            format!("updated {}", path.display()).yellow(),
+            Some(differences),
+        ),
+        SnapshotOutcome::Changed(differences) => (
+            format!("{} differences from {}", differences.len(), path.display()).red(),
+            Some(differences),
+        ),
+    };
+    eprintln!(
+        "{} {} {}",
+        "SNAPSHOT :".blue().bold(),
+        name.yellow(),
+        status
+    );
+
+    for difference in differences.into_iter().flatten() {
+        eprintln!("{}", difference.line());
+    }
+}
+
+/// The snapshots of one `--snapshot` run, kept for the summary
+pub struct SnapshotRun {
+    default_dir: PathBuf,
+    update: bool,
+    outcomes: Mutex<Vec<SnapshotOutcome>>,
+}
+
+impl SnapshotRun {
+    /// Snapshots go to `default_dir` unless configured, `update` accepts the changed ones
+    pub fn new(default_dir: PathBuf, update: bool) -> SnapshotRun {
+        SnapshotRun {
+            default_dir,
+            update,
+            outcomes: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Compare a response body with the `name` snapshot and print the outcome
+    pub fn check(&self, config: &SnapshotConfig, name: &str, body: &Value) -> io::Result<()> {
+        let path = config.path(&self.default_dir, name);
+        let outcome = config.check(&path, body, self.update)?;
+        display_snapshot(name, &path, &outcome);
+        self.outcomes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(outcome);
+        Ok(())
+    }
+
+    /// Whether a snapshot differed without `--update-snapshots`
+    pub fn failed(&self) -> bool {
+        self.outcomes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .any(SnapshotOutcome::failed)
+    }
+
+    /// Print how many snapshots matched, changed or were written
+    pub fn display_summary(&self) {
+        let outcomes = self.outcomes.lock().unwrap_or_else(|e| e.into_inner());
+        let failed = outcomes.iter().filter(|outcome| outcome.failed()).count();
+        let written = outcomes
+            .iter()
+            .filter(|outcome| {
+                matches!(
+                    outcome,
+                    SnapshotOutcome::Written | SnapshotOutcome::Updated(_)
+                )
+            })
+            .count();
+        eprintln!(
+            "{} {} matched, {} changed, {} written",
+            "SNAPSHOTS:".blue().bold(),
+            (outcomes.len() - failed - written).to_string().green(),
+            failed.to_string().red(),
+            written.to_string().yellow()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn path(text: &str) -> Vec<String> {
+        text.split('.')
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn double_star_matches_a_key_at_any_depth() {
+        assert!(matches_path("**.updated_at", &path("updated_at")));
+        assert!(matches_path(
+            "**.updated_at",
+            &path("data.0.meta.updated_at")
+        ));
+        assert!(!matches_path("**.updated_at", &path("data.updated_at_utc")));
+        assert!(!matches_path("**.updated_at", &path("updated_at.value")));
+        assert!(matches_path("data.**", &path("data.0.id")));
+    }
+
+    #[test]
+    fn single_star_matches_exactly_one_key_or_index() {
+        assert!(matches_path("data.*.id", &path("data.0.id")));
+        assert!(matches_path("data.*.id", &path("data.user.id")));
+        assert!(!matches_path("data.*.id", &path("data.id")));
+        assert!(!matches_path("data.*.id", &path("data.0.1.id")));
+        assert!(!matches_path("data.*.id", &path("meta.0.id")));
+    }
+
+    #[test]
+    fn no_pattern_matches_the_root() {
+        for pattern in ["**", "*", "", "**.**"] {
+            assert!(!matches_path(pattern, &[]), "{} matched the root", pattern);
+        }
+        let body = json!({"id": 1});
+        assert_eq!(
+            normalize(&body, &["**".to_string()], &mut Vec::new()),
+            json!({"id": IGNORED})
+        );
+    }
+
+    #[test]
+    fn ignored_values_are_replaced_but_their_keys_kept() {
+        let body = json!({
+            "data": [{"id": 1, "name": "a", "updated_at": "t1"}, {"id": 2, "meta": {"updated_at": "t2"}}],
+            "updated_at": "t3"
+        });
+        let ignore = ["**.updated_at".to_string(), "data.*.id".to_string()];
+        assert_eq!(
+            normalize(&body, &ignore, &mut Vec::new()),
+            json!({
+                "data": [
+                    {"id": IGNORED, "name": "a", "updated_at": IGNORED},
+                    {"id": IGNORED, "meta": {"updated_at": IGNORED}}
+                ],
+                "updated_at": IGNORED
+            })
+        );
+    }
+
+    fn differences(saved: Value, current: Value) -> Vec<Difference> {
+        super::differences(&saved, &current)
+    }
+
+    #[test]
+    fn arrays_that_grow_or_shrink_list_the_items() {
+        assert_eq!(
+            differences(json!({"items": [1, 2]}), json!({"items": [1, 2, 3, 4]})),
+            [
+                Difference::Added("items.2".to_string(), json!(3)),
+                Difference::Added("items.3".to_string(), json!(4)),
+            ]
+        );
+        assert_eq!(
+            differences(json!([{"id": 1}, {"id": 2}]), json!([{"id": 1}])),
+            [Difference::Removed("1".to_string(), json!({"id": 2}))]
+        );
+    }
+
+    #[test]
+    fn type_changes_and_key_changes_are_reported_by_path() {
+        assert_eq!(
+            differences(
+                json!({"id": 1, "tags": ["a"], "old": true}),
+                json!({"id": "1", "tags": {"0": "a"}, "new": null})
+            ),
+            [
+                Difference::Changed("id".to_string(), json!(1), json!("1")),
+                Difference::Removed("old".to_string(), json!(true)),
+                Difference::Changed("tags".to_string(), json!(["a"]), json!({"0": "a"})),
+                Difference::Added("new".to_string(), Value::Null),
+            ]
+        );
+        assert_eq!(
+            differences(json!({"a": 1}), json!([1])),
+            [Difference::Changed(
+                String::new(),
+                json!({"a": 1}),
+                json!([1])
+            )]
+        );
+        assert!(differences(
+            json!({"a": [1, {"b": null}]}),
+            json!({"a": [1, {"b": null}]})
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn a_snapshot_is_written_then_compared_then_updated() {
+        let dir = std::env::temp_dir().join(format!("rest_api_snapshot_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let config: SnapshotConfig =
+            serde_json::from_value(json!({"ignore": ["**.updated_at"]})).unwrap();
+        let file = config.path(&dir, "users/get");
+        assert_eq!(file, dir.join("users_get.json"));
+
+        let first = json!({"id": 1, "updated_at": "t1"});
+        assert!(matches!(
+            config.check(&file, &first, false).unwrap(),
+            SnapshotOutcome::Written
+        ));
+        let later = json!({"id": 1, "updated_at": "t2"});
+        assert!(matches!(
+            config.check(&file, &later, false).unwrap(),
+            SnapshotOutcome::Matched
+        ));
+
+        let changed = json!({"id": 2, "updated_at": "t3"});
+        let outcome = config.check(&file, &changed, false).unwrap();
+        assert!(outcome.failed());
+        assert!(matches!(
+            config.check(&file, &changed, true).unwrap(),
+            SnapshotOutcome::Updated(_)
+        ));
+        assert!(matches!(
+            config.check(&file, &changed, false).unwrap(),
+            SnapshotOutcome::Matched
+        ));
+    }
+
+    #[test]
+    fn a_configured_dir_is_found_next_to_the_collection() {
+        let collection_dir =
+            std::env::temp_dir().join(format!("rest_api_snapshot_dir_{}", std::process::id()));
+        fs::create_dir_all(collection_dir.join("golden")).unwrap();
+        let mut config: SnapshotConfig = serde_json::from_value(json!({"dir": "golden"})).unwrap();
+        config.resolve_files(&collection_dir);
+        assert_eq!(
+            config.path(Path::new("unused"), "user_get"),
+            collection_dir.join("golden/user_get.json")
+        );
+    }
+}
@@ -0,0 +1,429 @@
+use colored::Colorize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use serde_json::Value;
+
+use crate::file_ops::{expand_tilde, lock_for, read_from_file, write_atomic, write_atomic_locked};
+use crate::secrets::{decrypt, encrypt, SecretsConfig, MASK};
+
+/// Variables saved as {variable_dir}/NAME.txt, with `--var NAME=VALUE`
+/// overrides and run-scoped captured values that are never written to disk.
+/// With a `secrets` config, secret variables are saved encrypted as NAME.enc.
+#[derive(Clone, Debug, Default)]
+pub struct VariableStore {
+    dir: String,
+    overrides: HashMap<String, String>,
+    memory: Arc<Mutex<HashMap<String, String>>>,
+    secrets: Option<SecretsConfig>,
+    key: OnceLock<[u8; 32]>,
+    /// The secret values, read once and dropped whenever a variable changes
+    secret_values: Arc<Mutex<Option<Arc<Vec<String>>>>>,
+}
+
+impl VariableStore {
+    pub fn new(variable_dir: &str) -> Self {
+        VariableStore {
+            dir: variable_dir.to_string(),
+            overrides: HashMap::new(),
+            memory: Arc::default(),
+            secrets: None,
+            key: OnceLock::new(),
+            secret_values: Arc::default(),
+        }
+    }
+
+    /// A store for one data-file iteration: the row columns are added under the
+    /// `--var` overrides, and values captured by earlier iterations are dropped
+    pub fn for_row(&self, row: &[(String, String)]) -> Self {
+        let mut overrides: HashMap<String, String> = row.iter().cloned().collect();
+        overrides.extend(self.overrides.clone());
+        VariableStore {
+            overrides,
+            memory: Arc::default(),
+            secret_values: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    pub fn with_secrets(mut self, secrets: Option<SecretsConfig>) -> Self {
+        self.secrets = secrets;
+        self.secret_values = Arc::default();
+        self
+    }
+
+    pub fn with_overrides(mut self, overrides: &[(String, String)]) -> Self {
+        self.overrides.extend(overrides.iter().cloned());
+        self.secret_values = Arc::default();
+        self
+    }
+
+    fn file_path(&self, name: &str) -> PathBuf {
+        PathBuf::from(expand_tilde(&self.dir)).join(format!("{}.txt", name))
+    }
+
+    fn secret_path(&self, name: &str) -> PathBuf {
+        PathBuf::from(expand_tilde(&self.dir)).join(format!("{}.enc", name))
+    }
+
+    /// Whether a variable is encrypted on disk and masked in output
+    pub fn is_secret(&self, name: &str) -> bool {
+        self.secrets
+            .as_ref()
+            .is_some_and(|secrets| secrets.is_secret(name))
+    }
+
+    /// The encryption key, derived once per run
+    fn key(&self) -> io::Result<&[u8; 32]> {
+        if let Some(key) = self.key.get() {
+            return Ok(key);
+        }
+        let secrets = self.secrets.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "No secrets config in this collection",
+            )
+        })?;
+        let key = secrets.derive_key(&PathBuf::from(expand_tilde(&self.dir)))?;
+        Ok(self.key.get_or_init(|| key))
+    }
+
+    /// Read a variable, an override wins over a value captured in this run,
+    /// which wins over the saved file
+    pub fn get(&self, name: &str) -> io::Result<String> {
+        if let Some(value) = self.overrides.get(name) {
+            return Ok(value.clone());
+        }
+        if let Some(value) = self.remembered(name) {
+            return Ok(value);
+        }
+
+        let secret_path = self.secret_path(name);
+        if self.is_secret(name) && secret_path.exists() {
+            let content = read_from_file(&secret_path.to_string_lossy())?;
+            return decrypt(self.key()?, &content).map_err(|e| {
+                io::Error::new(
+                    e.kind(),
+                    format!("Error reading variable '{}': {}", secret_path.display(), e),
+                )
+            });
+        }
+
+        let file_path = self.file_path(name);
+        let content = read_from_file(&file_path.to_string_lossy()).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Error reading variable '{}': {}", file_path.display(), e),
+            )
+        })?;
+        Ok(content.trim().to_string())
+    }
+
+    /// Save a variable as {variable_dir}/NAME.txt, or encrypted as NAME.enc for a secret
+    pub fn set(&self, name: &str, value: &str) -> io::Result<()> {
+        let (file_path, content) = if self.is_secret(name) {
+            (self.secret_path(name), encrypt(self.key()?, value)?)
+        } else {
+            (self.file_path(name), value.to_string())
+        };
+        let lock = lock_for(&file_path)?;
+        write_atomic_locked(&file_path, content.as_bytes(), &lock)?;
+        self.forget_secret_values();
+
+        // A secret saved before the secrets config existed is no longer kept in plain text
+        if self.is_secret(name) && self.file_path(name).exists() {
+            fs::remove_file(self.file_path(name))?;
+        }
+        Ok(())
+    }
+
+    /// Keep a variable for the rest of this run without writing it to disk
+    pub fn remember(&self, name: &str, value: &str) {
+        if let Ok(mut memory) = self.memory.lock() {
+            memory.insert(name.to_string(), value.to_string());
+        }
+        self.forget_secret_values();
+    }
+
+    fn remembered(&self, name: &str) -> Option<String> {
+        self.memory.lock().ok()?.get(name).cloned()
+    }
+
+    /// Save a file inside variable_dir and return where it went.
+    /// `NAME.txt` goes through `set`, so a secret NAME is encrypted.
+    pub fn save_file(&self, filename: &str, value: &str) -> io::Result<PathBuf> {
+        if let Some(name) = filename.strip_suffix(".txt") {
+            self.set(name, value)?;
+            return Ok(if self.is_secret(name) {
+                self.secret_path(name)
+            } else {
+                self.file_path(name)
+            });
+        }
+
+        let file_path = PathBuf::from(expand_tilde(&self.dir)).join(filename);
+        write_atomic(&file_path, value.as_bytes())?;
+        Ok(file_path)
+    }
+
+    /// Delete a saved variable
+    pub fn unset(&self, name: &str) -> io::Result<()> {
+        self.forget_secret_values();
+        let secret_path = self.secret_path(name);
+        if secret_path.exists() {
+            fs::remove_file(secret_path)?;
+            if !self.file_path(name).exists() {
+                return Ok(());
+            }
+        }
+        fs::remove_file(self.file_path(name))
+    }
+
+    /// Every saved variable, sorted by name. Names keep their sub-directory, e.g. `users/ID`.
+    pub fn list(&self) -> io::Result<Vec<(String, String)>> {
+        self.names()?
+            .into_iter()
+            .map(|name| {
+                let value = self.get(&name)?;
+                Ok((name, value))
+            })
+            .collect()
+    }
+
+    /// Names of every saved variable, sorted
+    fn names(&self) -> io::Result<Vec<String>> {
+        let root = PathBuf::from(expand_tilde(&self.dir));
+        let mut names = Vec::new();
+        if root.is_dir() {
+            collect_variable_names(&root, &root, &mut names)?;
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Delete every saved variable and return how many were removed
+    pub fn clear(&self) -> io::Result<usize> {
+        let saved = self.names()?;
+        for name in &saved {
+            self.unset(name)?;
+        }
+        Ok(saved.len())
+    }
+
+    /// Replace every "{{VAR_NAME}}" inside a string with the variable value
+    pub fn resolve_str(&self, text: &str) -> io::Result<String> {
+        let mut resolved = String::new();
+        let mut rest = text;
+
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start..].find("}}") else {
+                break;
+            };
+            let var_name = rest[start + 2..start + end].trim();
+
+            resolved.push_str(&rest[..start]);
+            resolved.push_str(&self.get(var_name)?);
+            rest = &rest[start + end + 2..];
+        }
+
+        resolved.push_str(rest);
+        Ok(resolved)
+    }
+
+    /// Every known secret value, saved, captured or given with --var.
+    /// Walking variable_dir and decrypting happens once until a variable changes.
+    fn secret_values(&self) -> Arc<Vec<String>> {
+        if self.secrets.is_none() {
+            return Arc::default();
+        }
+        let mut cache = self.secret_values.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(values) = cache.as_ref() {
+            return values.clone();
+        }
+
+        let values = Arc::new(self.read_secret_values());
+        *cache = Some(values.clone());
+        values
+    }
+
+    fn read_secret_values(&self) -> Vec<String> {
+        let mut names = self.names().unwrap_or_default();
+        names.extend(self.overrides.keys().cloned());
+        if let Ok(memory) = self.memory.lock() {
+            names.extend(memory.keys().cloned());
+        }
+
+        names
+            .iter()
+            .filter(|name| self.is_secret(name))
+            .filter_map(|name| self.get(name).ok())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+
+    fn forget_secret_values(&self) {
+        if self.secrets.is_some() {
+            *self.secret_values.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        }
+    }
+
+    /// Replace every secret value inside a string with a mask
+    pub fn mask_str(&self, text: &str) -> String {
+        self.secret_values()
+            .iter()
+            .fold(text.to_string(), |text, value| {
+                text.replace(value.as_str(), MASK)
+            })
+    }
+
+    /// Copy of a JSON value with every secret value masked, for display
+    pub fn mask_json(&self, value: &Value) -> Value {
+        let secret_values = self.secret_values();
+        if secret_values.is_empty() {
+            return value.clone();
+        }
+        mask_value(value, &secret_values)
+    }
+
+    /// Print every saved variable, overrides included. Secret values are masked unless `reveal`.
+    pub fn display(&self, reveal: bool) -> io::Result<()> {
+        println!("{} {}", "VARIABLES:".blue().bold(), self.dir.yellow());
+
+        let mut variables = if reveal {
+            self.list()?
+        } else {
+            // Secret values are not decrypted when they are going to be masked anyway
+            self.names()?
+                .into_iter()
+                .map(|name| {
+                    let value = if self.is_secret(&name) {
+                        Ok(String::new())
+                    } else {
+                        self.get(&name)
+                    };
+                    value.map(|value| (name, value))
+                })
+                .collect::<io::Result<Vec<_>>>()?
+        };
+        for (name, value) in &self.overrides {
+            variables.retain(|(saved_name, _)| saved_name != name);
+            variables.push((name.clone(), value.clone()));
+        }
+        variables.sort();
+
+        for (name, value) in variables {
+            let source = if self.overrides.contains_key(&name) {
+                " (--var)"
+            } else {
+                ""
+            };
+            let value = if self.is_secret(&name) && !reveal {
+                MASK.to_string()
+            } else {
+                value
+            };
+            println!("{}={}{}", name.green(), value.yellow(), source.purple());
+        }
+        Ok(())
+    }
+}
+
+fn mask_value(value: &Value, secret_values: &[String]) -> Value {
+    match value {
+        Value::String(text) => {
+            Value::String(secret_values.iter().fold(text.clone(), |text, secret| {
+                text.replace(secret.as_str(), MASK)
+            }))
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| mask_value(item, secret_values))
+                .collect(),
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, item)| (key.clone(), mask_value(item, secret_values)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn collect_variable_names(root: &Path, dir: &Path, names: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_variable_names(root, &path, names)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "txt" || extension == "enc")
+        {
+            if let Ok(relative) = path.with_extension("").strip_prefix(root) {
+                names.push(relative.to_string_lossy().into_owned());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_captured_after_masking_are_masked_too() {
+        let dir = std::env::temp_dir().join(format!("rest_api_mask_{}", std::process::id()));
+        let secrets = serde_json::from_value(serde_json::json!({})).unwrap();
+        let variables = VariableStore::new(&dir.to_string_lossy())
+            .with_secrets(Some(secrets))
+            .with_overrides(&[("API_TOKEN".to_string(), "t0k3n".to_string())]);
+
+        assert_eq!(
+            variables.mask_str("Bearer t0k3n"),
+            format!("Bearer {}", MASK)
+        );
+        variables.remember("session_secret", "s3cr3t");
+        variables.remember("user_id", "42");
+        assert_eq!(
+            variables.mask_str("t0k3n s3cr3t 42"),
+            format!("{} {} 42", MASK, MASK)
+        );
+    }
+
+    #[test]
+    fn secret_variables_are_saved_encrypted_and_loaded_back() {
+        let dir = std::env::temp_dir().join(format!("rest_api_secret_vars_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let store = |passphrase: &str| {
+            let key_file = dir.join(format!("{}.key", passphrase));
+            fs::write(&key_file, passphrase).unwrap();
+            let secrets =
+                serde_json::from_value(serde_json::json!({"key_file": key_file.to_string_lossy()}))
+                    .unwrap();
+            VariableStore::new(&dir.join("vars").to_string_lossy()).with_secrets(Some(secrets))
+        };
+
+        let variables = store("right");
+        variables.set("API_TOKEN", "t0k3n").unwrap();
+        variables.set("user_id", "42").unwrap();
+
+        let saved = fs::read_to_string(dir.join("vars/API_TOKEN.enc")).unwrap();
+        assert!(!saved.contains("t0k3n"));
+        assert!(!dir.join("vars/API_TOKEN.txt").exists());
+        assert_eq!(
+            fs::read_to_string(dir.join("vars/user_id.txt")).unwrap(),
+            "42"
+        );
+
+        // A new run derives the key again from the same passphrase
+        assert_eq!(store("right").get("API_TOKEN").unwrap(), "t0k3n");
+        let error = store("wrong").get("API_TOKEN").unwrap_err();
+        assert!(error.to_string().contains("Wrong passphrase"));
+    }
+}
@@ -0,0 +1,264 @@
+use colored::Colorize;
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// A named set of request tags, run with `--workflow NAME`.
+/// Steps run as soon as every step in their `needs` answered with a 2xx,
+/// with at most `parallel` requests in flight.
+#[derive(Clone, Debug, Deserialize)]
+pub struct WorkflowConfig {
+    pub name: String,
+    #[serde(default = "default_parallel")]
+    pub parallel: usize,
+    pub steps: Vec<WorkflowStep>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WorkflowStep {
+    /// Defaults to the tag, set it when a tag appears twice in one workflow
+    id: Option<String>,
+    pub tag: String,
+    #[serde(default)]
+    needs: Vec<String>,
+}
+
+fn default_parallel() -> usize {
+    4
+}
+
+impl WorkflowStep {
+    pub fn id(&self) -> &str {
+        self.id.as_deref().unwrap_or(&self.tag)
+    }
+}
+
+/// How a step ended: its status, None when no response came back,
+/// or skipped because one of its `needs` did not succeed
+pub struct StepResult {
+    pub id: String,
+    pub status: Option<StatusCode>,
+    pub skipped: bool,
+    pub elapsed: Duration,
+}
+
+impl StepResult {
+    pub fn passed(&self) -> bool {
+        self.status.is_some_and(|status| status.is_success())
+    }
+}
+
+impl WorkflowConfig {
+    /// Every `needs` must name a step of this workflow, and the steps must not wait on each other
+    pub fn validate(&self) -> Result<(), String> {
+        let ids: HashMap<&str, &WorkflowStep> =
+            self.steps.iter().map(|step| (step.id(), step)).collect();
+        if ids.len() != self.steps.len() {
+            return Err(format!(
+                "Workflow '{}' has duplicate step ids, set \"id\" on repeated tags",
+                self.name
+            ));
+        }
+
+        for step in &self.steps {
+            for need in &step.needs {
+                if !ids.contains_key(need.as_str()) {
+                    return Err(format!(
+                        "Workflow '{}': step '{}' needs unknown step '{}'",
+                        self.name,
+                        step.id(),
+                        need
+                    ));
+                }
+            }
+        }
+
+        // Kahn's algorithm: whatever is left once nothing is ready is a cycle
+        let mut remaining: Vec<&WorkflowStep> = self.steps.iter().collect();
+        let mut done: Vec<&str> = Vec::new();
+        while !remaining.is_empty() {
+            let before = remaining.len();
+            remaining.retain(|step| {
+                if step.needs.iter().all(|need| done.contains(&need.as_str())) {
+                    done.push(step.id());
+                    false
+                } else {
+                    true
+                }
+            });
+            if remaining.len() == before {
+                let cycle: Vec<&str> = remaining.iter().map(|step| step.id()).collect();
+                return Err(format!(
+                    "Workflow '{}' has a dependency cycle between: {}",
+                    self.name,
+                    cycle.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run every step with `run_step`, independent steps concurrently, in dependency order
+    pub async fn run<F, Fut>(&self, parallel: usize, run_step: F) -> Vec<StepResult>
+    where
+        F: Fn(&WorkflowStep) -> Fut,
+        Fut: Future<Output = Option<StatusCode>>,
+    {
+        let parallel = parallel.max(1);
+        let mut results: Vec<StepResult> = Vec::new();
+        let mut pending: Vec<&WorkflowStep> = self.steps.iter().collect();
+        let mut running = FuturesUnordered::new();
+
+        loop {
+            // Skip the steps whose needs finished without a 2xx, and then their dependents
+            loop {
+                let failed: Vec<&str> = results
+                    .iter()
+                    .filter(|result| !result.passed())
+                    .map(|result| result.id.as_str())
+                    .collect();
+                let (skipped, waiting): (Vec<&WorkflowStep>, Vec<&WorkflowStep>) =
+                    pending.into_iter().partition(|step| {
+                        step.needs
+                            .iter()
+                            .any(|need| failed.contains(&need.as_str()))
+                    });
+                pending = waiting;
+                if skipped.is_empty() {
+                    break;
+                }
+                for step in skipped {
+                    let result = StepResult {
+                        id: step.id().to_string(),
+                        status: None,
+                        skipped: true,
+                        elapsed: Duration::ZERO,
+                    };
+                    display_step(&result);
+                    results.push(result);
+                }
+            }
+
+            // Start the steps whose needs all passed
+            while running.len() < parallel {
+                let passed: Vec<&str> = results
+                    .iter()
+                    .filter(|result| result.passed())
+                    .map(|result| result.id.as_str())
+                    .collect();
+                let Some(index) = pending.iter().position(|step| {
+                    step.needs
+                        .iter()
+                        .all(|need| passed.contains(&need.as_str()))
+                }) else {
+                    break;
+                };
+
+                let step = pending.remove(index);
+                let started = Instant::now();
+                let run = run_step(step);
+                running.push(async move {
+                    let status = run.await;
+                    StepResult {
+                        id: step.id().to_string(),
+                        status,
+                        skipped: false,
+                        elapsed: started.elapsed(),
+                    }
+                });
+            }
+
+            match running.next().await {
+                Some(result) => {
+                    display_step(&result);
+                    results.push(result);
+                }
+                None => break,
+            }
+        }
+
+        results
+    }
+}
+
+fn display_step(result: &StepResult) {
+    let outcome = match result.status {
+        _ if result.skipped => "skipped".yellow(),
+        Some(status) if status.is_success() => status.to_string().green(),
+        Some(status) => status.to_string().red(),
+        None => "no response".red(),
+    };
+    eprintln!(
+        "{} {} {} {}",
+        "STEP     :".blue().bold(),
+        result.id.yellow(),
+        outcome,
+        format!("({} ms)", result.elapsed.as_millis()).purple()
+    );
+}
+
+/// Print the pass/fail/skip totals of a workflow run
+pub fn display_workflow_summary(results: &[StepResult]) {
+    let passed = results.iter().filter(|result| result.passed()).count();
+    let skipped = results.iter().filter(|result| result.skipped).count();
+    eprintln!(
+        "{} {} passed, {} failed, {} skipped",
+        "TOTAL    :".blue().bold(),
+        passed.to_string().green(),
+        (results.len() - passed - skipped).to_string().red(),
+        skipped.to_string().yellow()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(tag: &str, needs: &[&str]) -> WorkflowStep {
+        WorkflowStep {
+            id: None,
+            tag: tag.to_string(),
+            needs: needs.iter().map(|need| need.to_string()).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn dependents_of_a_failed_step_are_skipped_transitively() {
+        let workflow = WorkflowConfig {
+            name: "chain".to_string(),
+            parallel: 4,
+            steps: vec![
+                step("a", &[]),
+                step("b", &["a"]),
+                step("c", &["b"]),
+                step("d", &[]),
+            ],
+        };
+
+        let results = workflow
+            .run(4, |step| {
+                let status = match step.tag.as_str() {
+                    "a" => StatusCode::INTERNAL_SERVER_ERROR,
+                    _ => StatusCode::OK,
+                };
+                async move { Some(status) }
+            })
+            .await;
+
+        let outcome = |id: &str| {
+            let result = results.iter().find(|result| result.id == id).unwrap();
+            (result.status, result.skipped)
+        };
+        assert_eq!(results.len(), 4);
+        assert_eq!(
+            outcome("a"),
+            (Some(StatusCode::INTERNAL_SERVER_ERROR), false)
+        );
+        assert_eq!(outcome("b"), (None, true));
+        assert_eq!(outcome("c"), (None, true));
+        assert_eq!(outcome("d"), (Some(StatusCode::OK), false));
+    }
+}
@@ -0,0 +1,275 @@
+use std::fs;
+use std::path::PathBuf;
+
+use rest_api::compare::Side;
+use rest_api::secrets::MASK;
+use rest_api::{AppMainRequest, RequestOverrides};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// A stand-in API: every response echoes the request line and the `x-token` header
+async fn echo_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    match socket.read(&mut buffer).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request).to_string();
+                let mut lines = request.lines();
+                let mut request_line = lines.next().unwrap_or_default().split(' ');
+                let token = lines
+                    .filter_map(|line| line.split_once(':'))
+                    .find(|(name, _)| name.eq_ignore_ascii_case("x-token"))
+                    .map(|(_, value)| value.trim().to_string());
+                let body = json!({
+                    "method": request_line.next(),
+                    "path": request_line.next(),
+                    "token": token,
+                    "data": {"id": 42}
+                })
+                .to_string();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nX-Request-Id: abc\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    format!("http://{}", address)
+}
+
+/// A collection file for `base_url` in a fresh directory, with its variable_dir next to it
+fn write_collection(name: &str, base_url: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rest_api_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let collection = json!({
+        "base_url": base_url,
+        "variable_dir": dir.join("_variables").to_string_lossy(),
+        "auth": {"type": "api_key", "name": "x-token", "value": "{{token}}"},
+        "requests": [{
+            "req_tag": "user_get",
+            "req_title": "Get a user",
+            "req_type": "GET",
+            "req_end_point": "/users/:id",
+            "req_query": {"fields": "name"},
+            "req_variable_response_value": "user_id.txt:data.id"
+        }]
+    });
+    let file = dir.join("api.json");
+    fs::write(&file, collection.to_string()).unwrap();
+    file
+}
+
+#[tokio::test]
+async fn a_loaded_collection_resolves_sends_and_captures() {
+    let base_url = echo_server().await;
+    let file = write_collection("send", &base_url);
+
+    let collection = AppMainRequest::load(&file.to_string_lossy()).unwrap();
+    let variables = collection
+        .variables()
+        .with_overrides(&[("token".to_string(), "t0k3n".to_string())]);
+    let request = collection.request("user_get").unwrap();
+    let overrides = RequestOverrides {
+        path: vec![("id".to_string(), "7".to_string())],
+        ..RequestOverrides::default()
+    };
+
+    let resolved = collection.resolve(request, &variables, &overrides).unwrap();
+    assert_eq!(resolved.method, "GET");
+    assert_eq!(resolved.url, format!("{}/users/7?fields=name", base_url));
+
+    let response = collection
+        .execute(request, &variables, &None, &overrides)
+        .await
+        .unwrap();
+    assert!(response.status.is_success());
+    assert_eq!(response.body["path"], "/users/7?fields=name");
+    assert_eq!(response.body["token"], "t0k3n");
+
+    collection.capture(request, &response, &variables).unwrap();
+    assert_eq!(variables.get("user_id").unwrap(), "42");
+}
+
+#[tokio::test]
+async fn compare_sends_each_side_with_its_own_collection_and_records_nothing() {
+    let (left_url, right_url) = (echo_server().await, echo_server().await);
+    let sides = [
+        (write_collection("compare_left", &left_url), "left-t0k3n"),
+        (write_collection("compare_right", &right_url), "right-t0k3n"),
+    ];
+    let overrides = RequestOverrides {
+        path: vec![("id".to_string(), "1".to_string())],
+        ..RequestOverrides::default()
+    };
+
+    let mut responses = Vec::new();
+    for (file, token) in &sides {
+        let collection = AppMainRequest::load(&file.to_string_lossy()).unwrap();
+        let variables = collection
+            .variables()
+            .with_overrides(&[("token".to_string(), token.to_string())]);
+        let side = Side::send(&collection, "user_get", &variables, &overrides)
+            .await
+            .unwrap();
+        assert!(collection.history().entries().unwrap().is_empty());
+        assert!(variables.get("user_id").is_err());
+        responses.push(side);
+    }
+
+    let (left, right) = (&responses[0], &responses[1]);
+    assert_eq!((left.status, right.status), (200, 200));
+    assert_eq!(left.body["token"], "left-t0k3n");
+    assert_eq!(right.body["token"], "right-t0k3n");
+    assert!(left.label.starts_with(&left_url));
+    assert!(right.label.starts_with(&right_url));
+}
+
+#[tokio::test]
+async fn a_secret_captured_from_the_response_is_saved_masked() {
+    let base_url = echo_server().await;
+    let dir = std::env::temp_dir().join(format!("rest_api_masked_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("secret.key"), "secret").unwrap();
+
+    let collection = json!({
+        "base_url": base_url,
+        "variable_dir": dir.join("_variables").to_string_lossy(),
+        "secrets": {"key_file": dir.join("secret.key").to_string_lossy()},
+        "requests": [{
+            "req_tag": "login",
+            "req_title": "Log in",
+            "req_type": "POST",
+            "req_end_point": "/login",
+            "req_variable_response_value": {"SESSION_TOKEN.txt": "header:X-Request-Id"}
+        }]
+    });
+    let file = dir.join("api.json");
+    fs::write(&file, collection.to_string()).unwrap();
+
+    let collection = AppMainRequest::load(&file.to_string_lossy()).unwrap();
+    let variables = collection.variables();
+    let overrides = RequestOverrides::default();
+    let request = collection.request("login").unwrap();
+    let resolved = collection.resolve(request, &variables, &overrides).unwrap();
+    let history = collection.history();
+    history
+        .execute(
+            &collection,
+            request,
+            &resolved,
+            &variables,
+            &None,
+            &overrides,
+        )
+        .await
+        .unwrap();
+    assert_eq!(variables.get("SESSION_TOKEN").unwrap(), "abc");
+
+    let entry = history.find(1).unwrap().unwrap();
+    let request_id = entry
+        .response_headers
+        .iter()
+        .find(|(name, _)| name == "x-request-id")
+        .map(|(_, value)| value.as_str());
+    assert_eq!(request_id, Some(MASK));
+}
+
+#[tokio::test]
+async fn a_collection_without_variable_dir_keeps_no_history() {
+    let base_url = echo_server().await;
+    let dir = std::env::temp_dir().join(format!("rest_api_no_history_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let collection = json!({
+        "base_url": base_url,
+        "requests": [{
+            "req_tag": "ping",
+            "req_title": "Ping",
+            "req_type": "GET",
+            "req_end_point": "/ping"
+        }]
+    });
+    let file = dir.join("api.json");
+    fs::write(&file, collection.to_string()).unwrap();
+
+    let collection = AppMainRequest::load(&file.to_string_lossy()).unwrap();
+    let variables = collection.variables();
+    let overrides = RequestOverrides::default();
+    let request = collection.request("ping").unwrap();
+    let resolved = collection.resolve(request, &variables, &overrides).unwrap();
+    let history = collection.history();
+    history
+        .execute(
+            &collection,
+            request,
+            &resolved,
+            &variables,
+            &None,
+            &overrides,
+        )
+        .await
+        .unwrap();
+
+    assert!(history.entries().unwrap().is_empty());
+    assert!(!PathBuf::from(".history.jsonl").exists());
+}
+
+#[tokio::test]
+async fn resend_sends_the_saved_request_even_when_the_tag_changed() {
+    let base_url = echo_server().await;
+    let file = write_collection("resend", &base_url);
+
+    let mut collection = AppMainRequest::load(&file.to_string_lossy()).unwrap();
+    let variables = collection
+        .variables()
+        .with_overrides(&[("token".to_string(), "t0k3n".to_string())]);
+    let overrides = RequestOverrides {
+        path: vec![("id".to_string(), "7".to_string())],
+        ..RequestOverrides::default()
+    };
+    let request = collection.request("user_get").unwrap().clone();
+    let resolved = collection
+        .resolve(&request, &variables, &overrides)
+        .unwrap();
+    let history = collection.history();
+    history
+        .execute(
+            &collection,
+            &request,
+            &resolved,
+            &variables,
+            &None,
+            &overrides,
+        )
+        .await
+        .unwrap();
+
+    collection.requests[0].req_end_point = "/accounts/:id".to_string();
+    let entry = history.find(1).unwrap().unwrap();
+    let response = history
+        .resend(&collection, &entry, &variables, &None)
+        .await
+        .unwrap();
+    assert_eq!(response.body["path"], "/users/7?fields=name");
+    assert_eq!(response.body["token"], "t0k3n");
+
+    let resent = history.find(2).unwrap().unwrap();
+    assert_eq!(resent.request.url, entry.request.url);
+    assert_eq!(resent.status, Some(200));
+}